@@ -5,8 +5,30 @@ local tools = {
 	"pen",
 	"line",
 	"rect",
+	"circle",
+	"arrow",
 }
 
+-- tools can be written in lua. update(state) is optional and called when the
+-- pointer moves, draw(state, path) builds the shape with move_to, line_to,
+-- quad_to, cubic_to, arc and close, and paints it with stroke or fill.
+-- state.start and state.pos are where we started and where the pointer is.
+sketchover.tool("arrow", {
+	draw = function(state, path)
+		local s, p = state.start, state.pos
+		local angle = math.atan2(p.y - s.y, p.x - s.x)
+		local head = 15
+		path:move_to(s.x, s.y)
+		path:line_to(p.x, p.y)
+		path:stroke()
+		path:move_to(p.x, p.y)
+		path:line_to(p.x - head * math.cos(angle - 0.4), p.y - head * math.sin(angle - 0.4))
+		path:line_to(p.x - head * math.cos(angle + 0.4), p.y - head * math.sin(angle + 0.4))
+		path:close()
+		path:fill()
+	end,
+})
+
 local idx = 1
 local current_tool = tools[idx]
 
@@ -89,6 +111,22 @@ end
 
 local outputs = {}
 
+-- shift straightens lines and squares rects, ctrl draws from the centre,
+-- false turns a behaviour off
+sketchover:modifier_map({ snap = "alt", from_centre = "ctrl" })
+
+-- snap to the grid of the background and line shapes up with the others,
+-- cb:toggle_snap() turns it on and off
+sketchover:snap({ grid = true, shapes = true, distance = 8 })
+
+-- handlers can also be registered by name, sketchover.on("keypress", fn).
+-- Every handler registered for an event is called, in the order they were
+-- registered. A handler that returns sketchover.STOP stops the handlers
+-- after it, anything else it returns is ignored. Registering returns a
+-- handle that can remove the handler:
+--   local handler = sketchover.keypress(function(cb, event, press) end)
+--   handler:unregister()
+
 sketchover.init(function(cb)
 	-- lets start
 	-- cb:pause()
@@ -97,8 +135,11 @@ end)
 sketchover.new_output(function(cb, info)
 	table.insert(outputs, info)
 
-	-- try to restore the output
-	-- info:restore(true)
+	-- info has the name, make, model and id of the output, kept up to date
+	-- while the output is there, and methods that act on it: save, restore, pause, unpause, clear, undo, set_fg, and
+	-- disable and enable to let the input through to the windows below.
+	-- save and restore take an optional path.
+	-- info:restore()
 end)
 
 sketchover.destroy_output(function(cb, id)
@@ -173,10 +214,9 @@ sketchover.keypress(function(cb, event, press)
 
 		if event.key == "XK_U" then
 			local _, output = find_output({ name = "eDP-1" })
-			if output then
-				-- We now undo on a specific screen
-				cb:undo(output.id)
-			else
+			-- commands return true, or nil and an error, wrap them in
+			-- assert to raise the error instead
+			if not (output and cb:undo(output.id)) then
 				-- if that screen isn't find, lets fall back
 				cb:undo()
 			end
@@ -190,6 +230,29 @@ sketchover.keypress(function(cb, event, press)
 		if event.key == "XK_c" then
 			cb:clear()
 		end
+		if event.key == "XK_a" then
+			cb:toggle_snap()
+		end
+
+		-- in handlers the shapes on an output can be listed and changed.
+		-- cb:shapes() returns { kind, age, bounds = { x, y, width, height }, style }
+		-- for every shape, oldest first, age is in seconds. cb:remove_shape(i), cb:restyle(i, style)
+		-- and cb:translate(i, dx, dy) change the i:th shape, and like the other
+		-- commands return true, or nil and what went wrong.
+		if event.key == "XK_b" then
+			-- turn everything red blue
+			for i, shape in ipairs(cb:shapes()) do
+				local c = shape.style.color
+				if c.r == 255 and c.g == 0 and c.b == 0 then
+					cb:restyle(i, { color = { r = 0, b = 255 } })
+				end
+			end
+		end
+
+		if event.key == "XK_r" then
+			-- run this script again, keeps the drawings
+			cb:reload()
+		end
 
 		if event.key == "XK_n" then
 			idx = idx % #tools + 1
@@ -222,10 +285,62 @@ sketchover.keypress(function(cb, event, press)
 	end
 end)
 
+-- the wheel changes the size of the pen, with ctrl it changes the color
+sketchover.scroll(function(cb, event)
+	if event.modifiers.ctrl then
+		p:next()
+	elseif event.direction == "up" then
+		p.draw.style.width = p.draw.style.width + 1
+	elseif event.direction == "down" then
+		p.draw.style.width = math.max(1, p.draw.style.width - 1)
+	end
+	-- change the shape we are drawing right now too
+	cb:set_style({ style = { width = p.draw.style.width }, color = p.draw.color })
+end)
+
+-- signals can be handled too: sigusr1, sigusr2, sigtstp, sighup and sigterm.
+-- After the handlers sigterm quits, sighup reloads and sigtstp toggles
+-- passthrough, unless a handler returns sketchover.STOP.
+-- `pkill -USR1 sketchover` clears the screen
+sketchover.signal("sigusr1", function(cb)
+	cb:clear()
+end)
+
+-- timers run on the event loop, sketchover.after(ms, fn) runs once and
+-- both return a timer that can be stopped with timer:cancel(). every
+-- needs an interval of at least 1 ms
+sketchover.every(5 * 60 * 1000, function(cb)
+	if save then
+		cb:save_all()
+	end
+end)
+
+-- requests from `sketchover msg` that the runtime doesn't handle itself,
+-- returning false or a string refuses the request
+sketchover.ipc(function(cb, request)
+	if request.command == "set_tool" then
+		for i, tool in ipairs(tools) do
+			if tool == request.tool then
+				idx = i
+				current_tool = tool
+				return true
+			end
+		end
+		return "no tool named " .. request.tool
+	end
+
+	if request.command == "set_color" then
+		p.draw.color = request.color
+	end
+end)
+
+-- called once when sketchover exits, by escape, cb:quit() or sigterm. The
+-- outputs are still there, so this is where to save them.
+sketchover.shutdown(function(cb)
+	if save then
+		cb:save_all()
+	end
+end)
+
 -- sketchover starts to run, the object is now locked until run returns.
 sketchover:run()
-
--- if save then
--- 	-- save all screens after this.
--- 	sketchover:save()
--- end
@@ -0,0 +1,147 @@
+// The C ABI native plugins are written against. Only plain C types cross
+// the boundary, so a plugin doesn't have to be built with the same rust
+// compiler as sketchover, or be written in rust at all.
+//
+// A plugin is a shared library that exports a function named
+// sketchover_plugin returning a Plugin. Sketchover checks abi_version and
+// then calls register, where the plugin adds its tools and hooks. Every
+// pointer passed to sketchover must stay valid until the library is
+// unloaded, or for states, until sketchover frees them.
+//
+// ABI_VERSION is bumped whenever anything in this file changes.
+
+use std::ffi::{c_char, c_void};
+
+/// The version of the ABI described here
+pub const ABI_VERSION: u32 = 1;
+
+/// The symbol sketchover looks for, a PluginEntry
+pub const ENTRY_SYMBOL: &[u8] = b"sketchover_plugin\0";
+
+pub type PluginEntry = unsafe extern "C" fn() -> *const Plugin;
+
+#[repr(C)]
+pub struct Plugin {
+    /// Must be ABI_VERSION, or the plugin isn't loaded
+    pub abi_version: u32,
+    /// A nul terminated name, used in the logs
+    pub name: *const c_char,
+    /// Called once when the plugin is loaded
+    pub register: unsafe extern "C" fn(registrar: *mut Registrar),
+}
+
+/// Passed to register, only valid during the call
+#[repr(C)]
+pub struct Registrar {
+    pub ctx: *mut c_void,
+    /// Returns false if there already is a tool with that name
+    pub add_tool: unsafe extern "C" fn(ctx: *mut c_void, tool: *const ToolDef) -> bool,
+    pub add_hooks: unsafe extern "C" fn(ctx: *mut c_void, hooks: *const Hooks),
+    /// Write a nul terminated message to the log of sketchover. The level
+    /// is 1 for errors up to 5 for traces, like in the log crate. Unlike
+    /// the rest of the registrar it can be kept and called for as long
+    /// as the plugin is loaded.
+    pub log: unsafe extern "C" fn(level: u32, message: *const c_char),
+}
+
+/// A tool, it's drawn with paths like the tools defined in lua. Only the
+/// paths are saved, so saves can be restored without the plugin.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ToolDef {
+    /// A nul terminated name, what the tool is selected with
+    pub name: *const c_char,
+    /// Start drawing at x, y. The state is passed to update and free.
+    pub start: unsafe extern "C" fn(x: f64, y: f64) -> *mut c_void,
+    /// The pointer moved to x, y. Paint what the shape looks like now.
+    pub update: unsafe extern "C" fn(state: *mut c_void, x: f64, y: f64, paths: *mut Paths),
+    pub free: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// The path builder passed to update, the same as the path in lua.
+/// stroke and fill paint the path built so far and start a new one.
+#[repr(C)]
+pub struct Paths {
+    pub ctx: *mut c_void,
+    pub move_to: unsafe extern "C" fn(ctx: *mut c_void, x: f32, y: f32),
+    pub line_to: unsafe extern "C" fn(ctx: *mut c_void, x: f32, y: f32),
+    pub quad_to: unsafe extern "C" fn(ctx: *mut c_void, cx: f32, cy: f32, x: f32, y: f32),
+    pub cubic_to: unsafe extern "C" fn(
+        ctx: *mut c_void,
+        cx1: f32,
+        cy1: f32,
+        cx2: f32,
+        cy2: f32,
+        x: f32,
+        y: f32,
+    ),
+    /// Centre, radius, start angle and sweep in radians
+    pub arc: unsafe extern "C" fn(ctx: *mut c_void, x: f32, y: f32, r: f32, start: f32, sweep: f32),
+    pub close: unsafe extern "C" fn(ctx: *mut c_void),
+    pub stroke: unsafe extern "C" fn(ctx: *mut c_void),
+    pub fill: unsafe extern "C" fn(ctx: *mut c_void),
+}
+
+/// Functions called on events, any of them can be null. They are called
+/// before the bindings of sketchover see the event.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Hooks {
+    /// Passed to all the hooks
+    pub data: *mut c_void,
+    pub new_output: Option<unsafe extern "C" fn(data: *mut c_void, host: *mut Host, output: u32)>,
+    pub destroy_output:
+        Option<unsafe extern "C" fn(data: *mut c_void, host: *mut Host, output: u32)>,
+    /// A key was pressed or released, return true to keep the bindings
+    /// from seeing it
+    pub key: Option<
+        unsafe extern "C" fn(data: *mut c_void, host: *mut Host, keysym: u32, press: bool) -> bool,
+    >,
+    /// A mouse button was pressed or released at x, y. The button is a
+    /// linux input event code, like BTN_LEFT. Return true to keep the
+    /// bindings from seeing it.
+    pub mouse: Option<
+        unsafe extern "C" fn(
+            data: *mut c_void,
+            host: *mut Host,
+            button: u32,
+            press: bool,
+            x: f64,
+            y: f64,
+        ) -> bool,
+    >,
+    /// One step of scrolling, dx and dy are -1, 0 or 1 and positive is
+    /// right and down. Return true to keep the bindings from seeing it.
+    pub scroll:
+        Option<unsafe extern "C" fn(data: *mut c_void, host: *mut Host, dx: i32, dy: i32) -> bool>,
+    /// A nul terminated json line sent to the control socket, before
+    /// sketchover reads it, so plugins can add their own commands. Return
+    /// a nul terminated json response, valid until the hook is called
+    /// again, or null to leave the request to sketchover.
+    pub request: Option<
+        unsafe extern "C" fn(
+            data: *mut c_void,
+            host: *mut Host,
+            request: *const c_char,
+        ) -> *const c_char,
+    >,
+    pub shutdown: Option<unsafe extern "C" fn(data: *mut c_void, host: *mut Host)>,
+    /// Called when the plugin is unloaded
+    pub free: Option<unsafe extern "C" fn(data: *mut c_void)>,
+}
+
+/// What the hooks can do with sketchover, only valid during the hook
+#[repr(C)]
+pub struct Host {
+    pub ctx: *mut c_void,
+    /// Run a request like `sketchover msg` does, a nul terminated json
+    /// request in and a json response out. The response is valid until
+    /// the next request or until the hook returns.
+    pub request: unsafe extern "C" fn(ctx: *mut c_void, request: *const c_char) -> *const c_char,
+}
+
+// so plugins can keep their definitions in statics, sketchover only
+// uses them from one thread
+unsafe impl Sync for Plugin {}
+unsafe impl Sync for ToolDef {}
+unsafe impl Sync for Hooks {}
@@ -0,0 +1,114 @@
+// An example plugin, it adds a star tool and clears the output when
+// Delete is pressed. Build it and copy it to the plugin dir:
+//
+//   cargo build --release -p sketchover-example-plugin
+//   cp target/release/libsketchover_example_plugin.so ~/.local/share/sketchover/plugins/
+
+use std::f32::consts::PI;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use sketchover_plugin::{Hooks, Host, Paths, Plugin, Registrar, ToolDef, ABI_VERSION};
+
+const XK_DELETE: u32 = 0xffff;
+
+static PLUGIN: Plugin = Plugin {
+    abi_version: ABI_VERSION,
+    name: c"example".as_ptr(),
+    register,
+};
+
+#[no_mangle]
+pub extern "C" fn sketchover_plugin() -> *const Plugin {
+    &PLUGIN
+}
+
+static STAR: ToolDef = ToolDef {
+    name: c"star".as_ptr(),
+    start,
+    update,
+    free,
+};
+
+static HOOKS: Hooks = Hooks {
+    data: ptr::null_mut(),
+    new_output: None,
+    destroy_output: None,
+    key: Some(key),
+    mouse: None,
+    scroll: None,
+    request: None,
+    shutdown: None,
+    free: None,
+};
+
+// The log crate of the plugin is its own, so the records are passed on
+// to the log of sketchover
+struct HostLog(unsafe extern "C" fn(level: u32, message: *const c_char));
+
+impl Log for HostLog {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if let Ok(message) = CString::new(format!("{}: {}", record.target(), record.args())) {
+            unsafe { (self.0)(record.level() as u32, message.as_ptr()) }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+unsafe extern "C" fn register(registrar: *mut Registrar) {
+    let registrar = &*registrar;
+    if log::set_boxed_logger(Box::new(HostLog(registrar.log))).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+    (registrar.add_tool)(registrar.ctx, &STAR);
+    (registrar.add_hooks)(registrar.ctx, &HOOKS);
+}
+
+// The state of a star is where it was started, the centre
+unsafe extern "C" fn start(x: f64, y: f64) -> *mut c_void {
+    Box::into_raw(Box::new((x as f32, y as f32))) as *mut c_void
+}
+
+unsafe extern "C" fn update(state: *mut c_void, x: f64, y: f64, paths: *mut Paths) {
+    let (cx, cy) = *(state as *const (f32, f32));
+    let paths = &*paths;
+    let r = (x as f32 - cx).hypot(y as f32 - cy);
+    // every other point is on the inner circle
+    for i in 0..10 {
+        let radius = if i % 2 == 0 { r } else { r * 0.4 };
+        let angle = i as f32 * PI / 5. - PI / 2.;
+        let (px, py) = (cx + radius * angle.cos(), cy + radius * angle.sin());
+        if i == 0 {
+            (paths.move_to)(paths.ctx, px, py);
+        } else {
+            (paths.line_to)(paths.ctx, px, py);
+        }
+    }
+    (paths.close)(paths.ctx);
+    (paths.stroke)(paths.ctx);
+}
+
+unsafe extern "C" fn free(state: *mut c_void) {
+    drop(Box::from_raw(state as *mut (f32, f32)));
+}
+
+unsafe extern "C" fn key(_: *mut c_void, host: *mut Host, keysym: u32, press: bool) -> bool {
+    if keysym != XK_DELETE {
+        return false;
+    }
+    if press {
+        let host = &*host;
+        let response = (host.request)(host.ctx, c"{\"command\":\"clear\"}".as_ptr());
+        let response = CStr::from_ptr(response).to_string_lossy();
+        if response.contains("\"error\"") {
+            log::error!("Couldn't clear: {response}");
+        }
+    }
+    true
+}
@@ -0,0 +1,373 @@
+// What is drawn between the foreground colour and the drawings of an
+// output. A board covers whatever is below sketchover, dim lets it show
+// through darkened, and the patterns are drawn in their own colour on top
+// of the foreground, so a white foreground and a grid is squared paper.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source, StrokeStyle};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::tools::draw::draw::parse_solid;
+
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Only the foreground colour
+    #[default]
+    None,
+    Whiteboard,
+    Blackboard,
+    /// A translucent black layer
+    Dim,
+    Grid,
+    Dots,
+    /// Horizontal lines, like a notebook
+    Ruled,
+    /// Vertical lines and lines at 30° both ways, meeting in triangles
+    Isometric,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 8] = [
+        Mode::None,
+        Mode::Whiteboard,
+        Mode::Blackboard,
+        Mode::Dim,
+        Mode::Grid,
+        Mode::Dots,
+        Mode::Ruled,
+        Mode::Isometric,
+    ];
+
+    /// The mode after this one in ALL, wrapping around
+    pub fn next(self) -> Mode {
+        let idx = Mode::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        Mode::ALL[(idx + 1) % Mode::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::None => "none",
+            Mode::Whiteboard => "whiteboard",
+            Mode::Blackboard => "blackboard",
+            Mode::Dim => "dim",
+            Mode::Grid => "grid",
+            Mode::Dots => "dots",
+            Mode::Ruled => "ruled",
+            Mode::Isometric => "isometric",
+        }
+    }
+
+    /// The mode called name, as written in the config
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+const WHITEBOARD: SolidSource = SolidSource {
+    r: 0xff,
+    g: 0xff,
+    b: 0xff,
+    a: 0xff,
+};
+
+const BLACKBOARD: SolidSource = SolidSource {
+    r: 0x1e,
+    g: 0x22,
+    b: 0x20,
+    a: 0xff,
+};
+
+// premultiplied, like all solid sources raqote draws
+const DIM: SolidSource = SolidSource {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0x80,
+};
+
+/// The smallest spacing, finer patterns take too long to draw
+pub const MIN_SPACING: f32 = 4.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Background {
+    pub mode: Mode,
+    /// Pixels between the lines or dots of a pattern, at least MIN_SPACING
+    #[serde(deserialize_with = "read_spacing")]
+    pub spacing: f32,
+    /// The colour of the pattern
+    #[serde(serialize_with = "write_color", deserialize_with = "read_color")]
+    pub color: SolidSource,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            mode: Mode::None,
+            spacing: 32.,
+            color: SolidSource {
+                r: 0x80,
+                g: 0x80,
+                b: 0x80,
+                a: 0x80,
+            },
+        }
+    }
+}
+
+/// A change to a background, the fields that are None are kept
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Change {
+    pub mode: Option<Mode>,
+    pub spacing: Option<f32>,
+    pub color: Option<SolidSource>,
+}
+
+fn write_color<S: Serializer>(color: &SolidSource, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        color.r, color.g, color.b, color.a
+    ))
+}
+
+fn read_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SolidSource, D::Error> {
+    let color = String::deserialize(deserializer)?;
+    parse_solid(&color).map_err(de::Error::custom)
+}
+
+fn read_spacing<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    check_spacing(f32::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+/// The spacing if it can be used, patterns closer than MIN_SPACING
+/// would have to draw a line or a dot for every few pixels
+pub fn check_spacing(spacing: f32) -> Result<f32, String> {
+    if !spacing.is_finite() {
+        Err(format!("{spacing} is not a spacing"))
+    } else if spacing < MIN_SPACING {
+        Err(format!("The spacing can't be less than {MIN_SPACING}"))
+    } else {
+        Ok(spacing)
+    }
+}
+
+impl Background {
+    /// Nothing is changed if the spacing can't be used
+    pub fn apply(&mut self, change: &Change) -> Result<(), String> {
+        let spacing = change.spacing.map(check_spacing).transpose()?;
+        if let Some(mode) = change.mode {
+            self.mode = mode;
+        }
+        if let Some(spacing) = spacing {
+            self.spacing = spacing;
+        }
+        if let Some(color) = change.color {
+            self.color = color;
+        }
+        Ok(())
+    }
+
+    /// The point of the pattern closest to pos. Backgrounds without a
+    /// pattern use the points of the grid, ruled lines only move y.
+    pub fn grid_point(&self, pos: (f64, f64)) -> (f64, f64) {
+        let spacing = self.spacing.max(MIN_SPACING) as f64;
+        let round = |v: f64, step: f64| (v / step).round() * step;
+        match self.mode {
+            Mode::Ruled => (pos.0, round(pos.1, spacing)),
+            Mode::Isometric => {
+                // the lines meet on the vertical lines, every half step
+                // apart and shifted by half a step on every other line
+                let half = spacing / 30f64.to_radians().cos() / 2.;
+                let col = (pos.0 / spacing).floor();
+                [col, col + 1.]
+                    .into_iter()
+                    .map(|col| {
+                        let shift = col.rem_euclid(2.) * half;
+                        (col * spacing, round(pos.1 - shift, 2. * half) + shift)
+                    })
+                    .min_by(|a, b| {
+                        let dist = |p: &(f64, f64)| (p.0 - pos.0).hypot(p.1 - pos.1);
+                        dist(a).total_cmp(&dist(b))
+                    })
+                    .unwrap_or(pos)
+            }
+            _ => (round(pos.0, spacing), round(pos.1, spacing)),
+        }
+    }
+
+    pub fn render(&self, dt: &mut DrawTarget<&mut [u32]>) {
+        let (width, height) = (dt.width() as f32, dt.height() as f32);
+        let fill = |dt: &mut DrawTarget<&mut [u32]>, color| {
+            dt.fill_rect(
+                0.,
+                0.,
+                width,
+                height,
+                &Source::Solid(color),
+                &DrawOptions::new(),
+            )
+        };
+        // the spacing is checked when it's set, but the field is public
+        let spacing = self.spacing.max(MIN_SPACING);
+        let mut pb = PathBuilder::new();
+        match self.mode {
+            Mode::None => return,
+            Mode::Whiteboard => return fill(dt, WHITEBOARD),
+            Mode::Blackboard => return fill(dt, BLACKBOARD),
+            Mode::Dim => return fill(dt, DIM),
+            Mode::Grid => {
+                lines(&mut pb, (0., 0.), (0., height), (spacing, 0.), width);
+                lines(&mut pb, (0., 0.), (width, 0.), (0., spacing), height);
+            }
+            Mode::Ruled => lines(&mut pb, (0., 0.), (width, 0.), (0., spacing), height),
+            Mode::Dots => {
+                let mut x = 0.;
+                while x <= width {
+                    let mut y = 0.;
+                    while y <= height {
+                        pb.rect(x - 1., y - 1., 2., 2.);
+                        y += spacing;
+                    }
+                    x += spacing;
+                }
+                dt.fill(
+                    &pb.finish(),
+                    &Source::Solid(self.color),
+                    &DrawOptions::new(),
+                );
+                return;
+            }
+            Mode::Isometric => {
+                // the lines of each slope are spacing apart, measured
+                // across them, and all three meet on the vertical lines
+                let rise = 30f32.to_radians().tan() * width;
+                let step = spacing / 30f32.to_radians().cos();
+                lines(&mut pb, (0., 0.), (0., height), (spacing, 0.), width);
+                let first = -(rise / step).ceil() * step;
+                lines(
+                    &mut pb,
+                    (0., first),
+                    (width, first + rise),
+                    (0., step),
+                    height - first,
+                );
+                lines(&mut pb, (0., 0.), (width, -rise), (0., step), height + rise);
+            }
+        }
+        let style = StrokeStyle {
+            width: 1.,
+            ..Default::default()
+        };
+        dt.stroke(
+            &pb.finish(),
+            &Source::Solid(self.color),
+            &style,
+            &DrawOptions::new(),
+        );
+    }
+}
+
+/// Add the line from start to end, and copies of it moved by step until
+/// they have moved len along it
+fn lines(pb: &mut PathBuilder, start: (f32, f32), end: (f32, f32), step: (f32, f32), len: f32) {
+    let step_len = step.0.hypot(step.1);
+    let mut moved = 0.;
+    while moved <= len {
+        let (dx, dy) = (step.0 * moved / step_len, step.1 * moved / step_len);
+        // on the pixel centres, so one pixel wide lines stay sharp
+        pb.move_to(start.0 + dx + 0.5, start.1 + dy + 0.5);
+        pb.line_to(end.0 + dx + 0.5, end.1 + dy + 0.5);
+        moved += step_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use raqote::DrawTarget;
+
+    use super::{Background, Change, Mode, MIN_SPACING};
+
+    fn render(background: &Background) -> Vec<u32> {
+        let mut buffer = vec![0u32; 32 * 32];
+        let mut dt = DrawTarget::from_backing(32, 32, &mut buffer[..]);
+        background.render(&mut dt);
+        buffer
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(Mode::Isometric.next(), Mode::None);
+        assert_eq!(Mode::from_name("dots"), Some(Mode::Dots));
+
+        let mut background = Background::default();
+        assert!(render(&background).iter().all(|&pixel| pixel == 0));
+        background.mode = Mode::Whiteboard;
+        assert!(render(&background).iter().all(|&pixel| pixel == 0xffffffff));
+
+        background.mode = Mode::Grid;
+        background.spacing = 8.;
+        let pixels = render(&background);
+        let alpha = |x: usize, y: usize| pixels[y * 32 + x] >> 24;
+        assert!(alpha(8, 3) > 0 && alpha(3, 16) > 0);
+        assert_eq!(alpha(3, 3), 0);
+
+        background.mode = Mode::Ruled;
+        let pixels = render(&background);
+        assert!(pixels[16 * 32 + 3] >> 24 > 0);
+        assert_eq!(pixels[3 * 32 + 8] >> 24, 0);
+
+        assert_eq!(background.grid_point((3., 13.)), (3., 16.));
+        background.mode = Mode::Grid;
+        assert_eq!(background.grid_point((3., 13.)), (0., 16.));
+
+        // the lines at 30° cross the second vertical line half way
+        // between the crossings on the first
+        background.mode = Mode::Isometric;
+        let half = 8. / 30f64.to_radians().cos() / 2.;
+        assert_eq!(background.grid_point((1., 1.)), (0., 0.));
+        let (x, y) = background.grid_point((7., half + 1.));
+        assert_eq!(x, 8.);
+        assert!((y - half).abs() < 1e-9);
+
+        background.mode = Mode::Ruled;
+        let json = serde_json::to_value(background).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "mode": "ruled", "spacing": 8., "color": "#80808080" })
+        );
+        let read: Background =
+            serde_json::from_value(serde_json::json!({ "mode": "dim" })).unwrap();
+        assert_eq!(read.mode, Mode::Dim);
+        assert_eq!(read.spacing, Background::default().spacing);
+        assert!(
+            serde_json::from_value::<Background>(serde_json::json!({ "spacing": 1. })).is_err()
+        );
+    }
+
+    #[test]
+    fn spacing() {
+        let mut background = Background::default();
+        let change = |mode, spacing| Change {
+            mode: Some(mode),
+            spacing: Some(spacing),
+            color: None,
+        };
+        assert!(background.apply(&change(Mode::Dots, 1.)).is_err());
+        assert!(background.apply(&change(Mode::Dots, f32::NAN)).is_err());
+        assert!(background
+            .apply(&change(Mode::Dots, f32::INFINITY))
+            .is_err());
+        assert_eq!(background, Background::default());
+        background.apply(&change(Mode::Dots, MIN_SPACING)).unwrap();
+        assert_eq!(background.spacing, MIN_SPACING);
+    }
+}
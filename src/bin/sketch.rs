@@ -1,131 +1,318 @@
+use std::path::PathBuf;
+
 use calloop::signals::Signal;
 use calloop::signals::Signals;
 use calloop::EventLoop;
-use sketchover::output::OutPut;
+use clap::Parser;
+use raqote::{SolidSource, StrokeStyle};
+use sketchover::board::BoardOp;
+use sketchover::config::{Args, Command, Config};
+use sketchover::ipc::{Request, Response};
+use sketchover::keymap::KeyMap;
+use sketchover::mousemap::{Mouse, MouseMap};
+use sketchover::output::{self, OutPut, Saved};
+use sketchover::plugin::{self, Plugins};
 use sketchover::runtime::Events;
 use sketchover::runtime::Runtime;
-use sketchover::tools::draw::draw::Draw;
-use sketchover::tools::draw::pen::Pen;
+use sketchover::tools::draw::draw::{parse_solid, Draw};
+use sketchover::tools::registry::{ToolRegistry, UnknownTool};
+use sketchover::tools::snap::Snap;
 use smithay_client_toolkit::seat::keyboard::KeyEvent;
-use xkbcommon::xkb::keysyms;
+use xdg::BaseDirectories;
+
+const MIN_SIZE: f32 = 0.5;
+const SAVE_FILE: &str = "save.json";
 
 struct Bindings {
-    outputs: Vec<String>,
-    saved: Vec<String>,
+    cfg: Config,
+    palette: Vec<SolidSource>,
+    fg: SolidSource,
+    alt_fg: SolidSource,
+    use_alt_fg: bool,
+    color: usize,
+    tool: usize,
+    size: f32,
+    saved: Vec<Saved>,
     save: bool,
 }
 
-pub enum MouseEvent {
-    BtnLeft,
-    BtnRight,
-    BtnMiddle,
-    BtnSide,
-    BtnExtra,
-    BtnForward,
-    BtnBack,
-    BtnTask,
-    Raw(u32),
+impl Bindings {
+    fn new(cfg: Config, tools: &ToolRegistry) -> anyhow::Result<Self> {
+        let color =
+            |str: &str| parse_solid(str).map_err(|e| anyhow::anyhow!("{str} is not a color: {e}"));
+        let palette = cfg
+            .palette
+            .iter()
+            .map(|c| color(c))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if palette.is_empty() {
+            anyhow::bail!("The palette needs at least one color");
+        }
+        if cfg.tools.is_empty() {
+            anyhow::bail!("At least one tool is needed");
+        }
+        if let Some(tool) = cfg.tools.iter().find(|tool| !tools.contains(tool)) {
+            anyhow::bail!("{}", UnknownTool(tool.clone()));
+        }
+        Ok(Bindings {
+            palette,
+            fg: color(&cfg.foreground)?,
+            alt_fg: color(&cfg.alt_foreground)?,
+            use_alt_fg: false,
+            color: 0,
+            tool: 0,
+            size: cfg.size,
+            saved: Vec::new(),
+            save: cfg.save_on_exit,
+            cfg,
+        })
+    }
+
+    fn fg(&self) -> SolidSource {
+        if self.use_alt_fg {
+            self.alt_fg
+        } else {
+            self.fg
+        }
+    }
+
+    fn draw(&self) -> Draw {
+        Draw {
+            style: StrokeStyle {
+                width: self.size,
+                ..Default::default()
+            },
+            color: self.palette[self.color],
+        }
+    }
+}
+
+fn save_path() -> Option<PathBuf> {
+    let xdg_dirs = BaseDirectories::with_prefix("sketchover").ok()?;
+    xdg_dirs.place_data_file(SAVE_FILE).ok()
 }
 
-impl From<u32> for MouseEvent {
-    fn from(value: u32) -> Self {
-        match value {
-            0x110 => MouseEvent::BtnLeft,
-            0x111 => MouseEvent::BtnRight,
-            0x112 => MouseEvent::BtnMiddle,
-            0x113 => MouseEvent::BtnSide,
-            0x114 => MouseEvent::BtnExtra,
-            0x115 => MouseEvent::BtnForward,
-            0x116 => MouseEvent::BtnBack,
-            0x117 => MouseEvent::BtnTask,
-            raw => MouseEvent::Raw(raw),
+fn command(r: &mut Runtime<Bindings>, command: &Command) {
+    let colors = r.data.palette.len();
+    let tools = r.data.cfg.tools.len();
+    match command {
+        Command::Clear => r.clear(false),
+        Command::Undo => r.undo(),
+        Command::SetColor(idx) if *idx < colors => r.data.color = *idx,
+        Command::NextColor => r.data.color = (r.data.color + 1) % colors,
+        Command::PrevColor => r.data.color = (r.data.color + colors - 1) % colors,
+        Command::SetTool(idx) if *idx < tools => r.data.tool = *idx,
+        Command::NextTool => r.data.tool = (r.data.tool + 1) % tools,
+        Command::PrevTool => r.data.tool = (r.data.tool + tools - 1) % tools,
+        Command::SetColor(idx) | Command::SetTool(idx) => {
+            log::warn!("{command:?}: {idx} is out of range")
+        }
+        Command::IncreaseSize(step) => r.data.size += step,
+        Command::DecreaseSize(step) => r.data.size = (r.data.size - step).max(MIN_SIZE),
+        Command::TogglePause | Command::SetPause | Command::SetUnpause => {
+            if let Some(idx) = r.locate_output_idx(None) {
+                let pause = match command {
+                    Command::SetPause => true,
+                    Command::SetUnpause => false,
+                    _ => r.outputs()[idx].screencopy.is_none(),
+                };
+                r.set_pause(pause, idx);
+            }
+        }
+        Command::TogglePassthrough => r.set_passthrough(!r.passthrough()),
+        Command::ToggleSnap => {
+            let snap = *r.snap();
+            r.set_snap(Snap {
+                enabled: !snap.enabled,
+                ..snap
+            });
+        }
+        Command::Save => match save_path() {
+            Some(path) => {
+                if let Err(e) = r.save_all(&path) {
+                    log::error!("Couldn't save to {}: {e}", path.display());
+                }
+            }
+            None => log::error!("Couldn't find a place for the save file"),
+        },
+        Command::ToggleFg => {
+            r.data.use_alt_fg = !r.data.use_alt_fg;
+            let fg = r.data.fg();
+            for output in r.outputs_mut() {
+                output.set_fg(fg);
+            }
+        }
+        Command::NewBoard | Command::NextBoard | Command::PrevBoard | Command::DeleteBoard => {
+            let op = match command {
+                Command::NewBoard => BoardOp::New(None),
+                Command::NextBoard => BoardOp::Next,
+                Command::PrevBoard => BoardOp::Prev,
+                _ => BoardOp::Delete,
+            };
+            if let Some(idx) = r.locate_output_idx(None) {
+                if let Err(e) = r.board(idx, op) {
+                    log::warn!("{command:?}: {e}");
+                }
+            }
+        }
+        Command::SetBackground(mode) => {
+            if let Some(output) = r.locate_output(None) {
+                output.background.mode = *mode;
+            }
+        }
+        Command::NextBackground => {
+            if let Some(output) = r.locate_output(None) {
+                output.background.mode = output.background.mode.next();
+            }
+        }
+        Command::DrawStart => {
+            let name = &r.data.cfg.tools[r.data.tool];
+            // tools are checked when the config is loaded
+            let pos = r.pos();
+            match r.tools().create(name, pos, r.data.draw()) {
+                Ok(tool) => r.start_drawing(tool, pos),
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        Command::Combo(commands) => {
+            for cmd in commands {
+                self::command(r, cmd);
+            }
         }
+        Command::Nop => {}
+        Command::Quit => r.exit(),
+    }
+    if matches!(
+        command,
+        Command::SetColor(_)
+            | Command::NextColor
+            | Command::PrevColor
+            | Command::IncreaseSize(_)
+            | Command::DecreaseSize(_)
+    ) {
+        restyle(r);
+    }
+}
+
+/// Change the shape being drawn too, not only the next one
+fn restyle(r: &mut Runtime<Bindings>) {
+    let draw = r.data.draw();
+    if let Some(active) = r.active_draw() {
+        active.style.width = draw.style.width;
+        active.color = draw.color;
     }
 }
 
 impl Events for Bindings {
     fn new_output(r: &mut Runtime<Self>, output: &mut OutPut) {
-        let name = output.name();
-        if r.data.saved.contains(&name) {
-            // we have saved data, lets restore it
-            // output.restore("");
-        }
-
-        r.data.outputs.push(name);
+        output.set_fg(r.data.fg());
+        output.background = r.data.cfg.background;
+        output.restore_saved(output::restore(&mut r.data.saved, &output.info));
     }
 
     fn keybinding(r: &mut Runtime<Self>, event: KeyEvent, press: bool) {
-        let modifier = r.modifiers();
-
         if !press {
             return;
         }
 
-        match event.keysym.raw() {
-            keysyms::KEY_Q if modifier.shift => {
-                r.exit();
-            }
-            keysyms::KEY_S => {
-                r.data.save = true;
-            }
-            keysyms::KEY_P => {
-                let idx = r
-                    .locate_output_idx(None)
-                    .expect("Can't find screen to pause");
-                r.set_pause(true, idx);
-            }
-            keysyms::KEY_N => {
-                let idx = r
-                    .locate_output_idx(None)
-                    .expect("Can't find screen to pause");
-                r.set_pause(false, idx);
-            }
-            _ => {}
+        let key = KeyMap {
+            key: event.keysym,
+            modifier: r.modifiers(),
+        };
+        if let Some(cmd) = r.data.cfg.key_command(key).cloned() {
+            command(r, &cmd);
         }
     }
 
     fn mousebinding(r: &mut Runtime<Self>, button: u32, press: bool) {
-        let mouse: MouseEvent = button.into();
-
         if !press {
             r.stop_drawing();
             return;
         }
 
-        #[allow(clippy::single_match)]
-        match mouse {
-            MouseEvent::BtnLeft => {
-                r.start_drawing(Box::new(Pen::new(r.pos(), Draw::default())));
+        let mouse = MouseMap::new(Mouse::Button(button.into()), r.modifiers());
+        if let Some(cmd) = r.data.cfg.mouse_command(mouse).cloned() {
+            command(r, &cmd);
+        }
+    }
+
+    fn scrollbinding(r: &mut Runtime<Self>, scroll: MouseMap) {
+        if let Some(cmd) = r.data.cfg.mouse_command(scroll).cloned() {
+            command(r, &cmd);
+        }
+    }
+
+    fn request(r: &mut Runtime<Self>, request: &Request) -> Response {
+        let Request::SetTool { tool } = request else {
+            return Response::error(format!("{request:?} is not supported"));
+        };
+        match r.data.cfg.tools.iter().position(|t| t == tool) {
+            Some(idx) => r.data.tool = idx,
+            // a tool that isn't in the config joins the ones we cycle through
+            None if r.tools().contains(tool) => {
+                r.data.cfg.tools.push(tool.clone());
+                r.data.tool = r.data.cfg.tools.len() - 1;
             }
-            _ => {}
+            None => return Response::error(UnknownTool(tool.clone()).to_string()),
+        }
+        Response::Ok
+    }
+
+    fn shutdown(r: &mut Runtime<Self>) {
+        if r.data.save {
+            command(r, &Command::Save);
         }
     }
 }
 
-fn main() {
-    let b = Bindings {
-        outputs: Vec::new(),
-        saved: Vec::new(),
-        save: false,
-    };
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    let cfg = Config::load(args).map_err(|e| anyhow::anyhow!("Couldn't load config: {e}"))?;
+    let mut tools = ToolRegistry::default();
+    let mut plugins = Plugins::default();
+    match plugin::plugin_dir() {
+        Ok(dir) => plugins.load_dir(&dir, &mut tools),
+        Err(e) => log::warn!("Couldn't find the plugin dir: {e}"),
+    }
+    let mut b = Bindings::new(cfg, &tools)?;
+
+    if let Some(path) = save_path().filter(|p| p.exists()) {
+        match output::load(&path) {
+            Ok(saved) => {
+                b.saved = saved;
+                if b.cfg.delete_save_on_resume {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+            // keep a save that can't be read, it may be all there is
+            Err(e) => log::error!("Couldn't resume from {}: {e}", path.display()),
+        }
+    }
+
+    let scroll_threshold = b.cfg.scroll_threshold;
+    let modifier_map = b.cfg.modifier_map.clone();
+    let snap = b.cfg.snap;
     let mut rt = Runtime::init(b);
+    *rt.plugins_mut() = plugins;
+    *rt.tools_mut() = tools;
+    rt.set_scroll_threshold(scroll_threshold);
+    rt.set_modifier_map(modifier_map);
+    rt.set_snap(snap);
     let event_loop = EventLoop::try_new().expect("couldn't create event-loop");
 
     event_loop
         .handle()
         .insert_source(
-            Signals::new(&[Signal::SIGTSTP]).unwrap(),
-            move |evt, &mut (), runtime: &mut Runtime<Bindings>| {
-                if evt.signal() == Signal::SIGTSTP {
-                    // runtime.set_passthrough(true);
-                }
+            Signals::new(&[Signal::SIGTSTP, Signal::SIGTERM]).unwrap(),
+            move |evt, &mut (), runtime: &mut Runtime<Bindings>| match evt.signal() {
+                Signal::SIGTSTP => runtime.set_passthrough(!runtime.passthrough()),
+                // exit through the event loop, so we save on exit
+                Signal::SIGTERM => runtime.exit(),
+                _ => {}
             },
         )
         .expect("Unable to configure signal handler");
     rt.run(event_loop);
-
-    if rt.data.save {
-        // the user wants to save on exit and we have exited.
-        // rt.save("");
-    }
+    Ok(())
 }
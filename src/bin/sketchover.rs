@@ -1,26 +1,101 @@
-use std::path::Path;
-use std::rc::Rc;
+use std::ffi::c_void;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
 
-use calloop::channel::SyncSender;
-use calloop::EventLoop;
-use mlua::{Error, Function, IntoLuaMulti, Table, Value};
-use mlua::{Lua, UserData, UserDataMethods};
+use calloop::channel::Sender;
+use calloop::signals::{Signal, Signals};
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, LoopHandle, RegistrationToken};
+use clap::{Parser, Subcommand};
+use hex_color::HexColor;
+use mlua::{AnyUserData, Error, Function, IntoLuaMulti, MultiValue, RegistryKey, Table, Value};
+use mlua::{LightUserData, Lua, UserData, UserDataMethods};
 use raqote::{LineCap, LineJoin, SolidSource, StrokeStyle};
+use sketchover::background::{check_spacing, Change, Mode};
+use sketchover::board::BoardOp;
+use sketchover::headless::Headless;
+use sketchover::ipc::{self, Eval, Request, Response};
+use sketchover::luatypes::LuaTypes;
+use sketchover::mousemap::{Mouse, MouseMap};
 use sketchover::output::OutPut;
+use sketchover::plugin;
 use sketchover::runtime::Events;
 use sketchover::runtime::Runtime;
+use sketchover::tools::behaviour::{Modifier, ModifierMap};
 use sketchover::tools::draw::draw::Draw;
-use sketchover::tools::draw::line::Line;
-use sketchover::tools::draw::pen::Pen;
-use sketchover::tools::draw::rekt::Rect;
+use sketchover::tools::draw::scripted::{Paint, PathOp, Script, Scripted};
+use sketchover::tools::registry::ToolRegistry;
+use sketchover::tools::snap::Snap;
+use sketchover::tools::Tool;
 use smithay_client_toolkit::output::OutputInfo;
 use smithay_client_toolkit::seat::keyboard::{KeyEvent, Modifiers};
 use wayland_client::protocol::wl_output::{Subpixel, Transform};
 use xdg::BaseDirectories;
 
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The lua script to run, defaults to $XDG_CONFIG_HOME/sketchover/init.lua
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Reload the script when it changes
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Print annotations of the lua api for lua-language-server and exit
+    #[arg(long)]
+    emit_lua_types: bool,
+
+    /// Accept lua chunks on a socket, see `sketchover repl`
+    #[arg(long)]
+    repl: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Send a command to a running sketchover
+    Msg {
+        #[command(subcommand)]
+        request: Request,
+    },
+    /// Evaluate lua in a sketchover started with --repl
+    Repl,
+    /// Render a save file or a script of events to png, one image per output
+    Render {
+        /// The save file or script to render
+        input: PathBuf,
+        /// The input is a json list of events instead of a save file
+        #[arg(short, long)]
+        script: bool,
+        /// Directory to write the images to
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+        /// Size of outputs in save files that don't store it
+        #[arg(long, default_value_t = 1920)]
+        width: u32,
+        #[arg(long, default_value_t = 1080)]
+        height: u32,
+    },
+}
+
+/// Where save and restore read and write, unless given a path
+const SAVE_FILE: &str = "sketchover";
+
+type Handle = LoopHandle<'static, Runtime<LuaBindings>>;
+
 struct LuaBindings {
     lua: Rc<Lua>,
-    sender: Option<Rc<SyncSender<Message>>>,
+    sender: Rc<Sender<Message>>,
+    handle: Handle,
+    /// The script to run again when reloading, None is the default script
+    script: Option<PathBuf>,
 }
 
 enum Message {
@@ -29,19 +104,24 @@ enum Message {
     Undo(Option<u32>),
     Pause(Option<u32>),
     Unpause(Option<u32>),
-    Save(Option<u32>),
+    Save(Option<u32>, PathBuf),
+    Restore(Option<u32>, PathBuf),
     SaveAll,
+    Passthrough(bool, Option<u32>),
+    Board(Option<u32>, BoardOp),
+    SetBackground(Option<u32>, Change),
 
     SetFg(SolidSource, Option<u32>),
     StopDraw,
     Drawing(String, (f64, f64), Draw),
+    // the table is kept in the registry until the style is applied
+    SetStyle(RegistryKey),
+    ModifierMap(ModifierMap),
+    Snap(Snap),
+    ToggleSnap,
+    Reload,
 }
 
-impl LuaBindings {
-    fn new(lua: Rc<Lua>) -> Self {
-        LuaBindings { lua, sender: None }
-    }
-}
 macro_rules! set {
     ($field:expr, $result:expr) => {
         if let Ok(value) = $result {
@@ -50,96 +130,410 @@ macro_rules! set {
     };
 }
 
-struct RuntimeData(Runtime<LuaBindings>);
+type Running = (
+    Runtime<LuaBindings>,
+    EventLoop<'static, Runtime<LuaBindings>>,
+);
+
+/// The events that can be handled with sketchover.on(name, fn), each
+/// also has a function of its own
+const EVENTS: [&str; 8] = [
+    "init",
+    "keypress",
+    "new_output",
+    "destroy_output",
+    "mousepress",
+    "scroll",
+    "ipc",
+    "shutdown",
+];
+
+// The sketchover module. run takes the runtime out, a reloaded script
+// doesn't get one since we are already running.
+struct RuntimeData {
+    sender: Rc<Sender<Message>>,
+    running: Option<Running>,
+}
 
 impl UserData for RuntimeData {
+    fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field("STOP", stop_value());
+    }
+
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_function("init", |lua, func: Function| {
-            register_event(lua, ("init".to_owned(), func))?;
-            Ok(())
+            register_event(lua, ("init".to_owned(), func))
         });
         methods.add_function("keypress", |lua, func: Function| {
-            register_event(lua, ("keypress".to_owned(), func))?;
-            Ok(())
+            register_event(lua, ("keypress".to_owned(), func))
         });
         methods.add_function("new_output", |lua, func: Function| {
-            register_event(lua, ("new_output".to_owned(), func))?;
-            Ok(())
+            register_event(lua, ("new_output".to_owned(), func))
         });
         methods.add_function("destroy_output", |lua, func: Function| {
-            register_event(lua, ("destroy_output".to_owned(), func))?;
-            Ok(())
+            register_event(lua, ("destroy_output".to_owned(), func))
         });
         methods.add_function("mousepress", |lua, func: Function| {
-            register_event(lua, ("mousepress".to_owned(), func))?;
-            Ok(())
+            register_event(lua, ("mousepress".to_owned(), func))
+        });
+        methods.add_function("scroll", |lua, func: Function| {
+            register_event(lua, ("scroll".to_owned(), func))
+        });
+        methods.add_function("ipc", |lua, func: Function| {
+            register_event(lua, ("ipc".to_owned(), func))
+        });
+        methods.add_function("shutdown", |lua, func: Function| {
+            register_event(lua, ("shutdown".to_owned(), func))
+        });
+        methods.add_function("on", |lua, (name, func): (String, Function)| {
+            if !EVENTS.contains(&name.as_str()) {
+                return Err(Error::RuntimeError(format!("{name} is not an event")));
+            }
+            register_event(lua, (name, func))
+        });
+        methods.add_function("after", |lua, (ms, func): (u64, Function)| {
+            add_timer(lua, ms, func, false)
+        });
+        methods.add_function("every", |lua, (ms, func): (u64, Function)| {
+            add_timer(lua, ms, func, true)
+        });
+        methods.add_function("tool", |lua, (name, def): (String, Table)| {
+            register_tool(lua, name, def)
+        });
+        methods.add_function("signal", |lua, (name, func): (String, Function)| {
+            if !SIGNALS.iter().any(|(_, n)| *n == name) {
+                return Err(Error::RuntimeError(format!("{name} is not a signal")));
+            }
+            register_event(lua, (format!("signal-{name}"), func))
+        });
+        methods.add_method("modifier_map", |_, data, table: Table| {
+            let mut map = ModifierMap::default();
+            set_modifier(&mut map.straighten, table.get("straighten")?)?;
+            set_modifier(&mut map.square, table.get("square")?)?;
+            set_modifier(&mut map.from_centre, table.get("from_centre")?)?;
+            set_modifier(&mut map.snap, table.get("snap")?)?;
+            Ok(status(queue(&data.sender, Message::ModifierMap(map))))
+        });
+        // what drawing snaps to, the fields left out get their default
+        // and snapping is off unless enabled is set
+        methods.add_method("snap", |_, data, table: Table| {
+            let default = Snap::default();
+            let snap = Snap {
+                enabled: table
+                    .get::<_, Option<_>>("enabled")?
+                    .unwrap_or(default.enabled),
+                grid: table.get::<_, Option<_>>("grid")?.unwrap_or(default.grid),
+                shapes: table
+                    .get::<_, Option<_>>("shapes")?
+                    .unwrap_or(default.shapes),
+                distance: table
+                    .get::<_, Option<_>>("distance")?
+                    .unwrap_or(default.distance),
+            };
+            Ok(status(queue(&data.sender, Message::Snap(snap))))
         });
         // methods.add_function("remove_output", |lua, func: Function| {
         //     register_event(lua, ("remove_output".to_owned(), func))?;
         //     Ok(())
         // });
 
-        methods.add_method_mut("run", |_, rt, ()| {
-            let event_loop = EventLoop::try_new().expect("couldn't create event-loop");
-            let (sender, receiver) = calloop::channel::sync_channel::<Message>(3);
-            event_loop
-                .handle()
-                .insert_source(
-                    receiver,
-                    |event, _, rt: &mut Runtime<LuaBindings>| match event {
-                        calloop::channel::Event::Msg(m) => match m {
-                            Message::Clear(id) => {
-                                let output = rt.locate_output(id).expect("couldn't find screen");
-                                output.clear();
-                            }
-                            Message::Quit => rt.exit(),
-                            Message::Undo(id) => {
-                                let output = rt.locate_output(id).unwrap();
-                                output.undo();
-                            }
-                            Message::Unpause(id) => {
-                                let id = rt
-                                    .locate_output_idx(id)
-                                    .expect("Can't find screen to unpause");
-                                rt.set_pause(false, id);
-                            }
-
-                            Message::Pause(id) => {
-                                let id = rt
-                                    .locate_output_idx(id)
-                                    .expect("Can't find screen to pause");
-                                rt.set_pause(true, id);
-                            }
-                            Message::SetFg(solid, id) => {
-                                let output = rt.locate_output(id).unwrap();
-                                output.set_fg(solid);
-                            }
-                            Message::Save(id) => {
-                                let output = rt.locate_output(id).unwrap();
-                                output.save("sketchover").expect("couldn't save output");
-                            }
-                            Message::SaveAll => {
-                                rt.save_all("sketchover").expect("Couldn't save all output");
-                            }
-                            Message::StopDraw => rt.stop_drawing(),
-                            Message::Drawing(s, pos, draw) => {
-                                // println!("draw: {:?}", draw);
-                                match s.as_ref() {
-                                    "pen" => rt.start_drawing(Box::new(Pen::new(pos, draw))),
-                                    "rect" => rt.start_drawing(Box::new(Rect::new(pos, draw))),
-                                    "line" => rt.start_drawing(Box::new(Line::new(pos, draw))),
-                                    _ => panic!("tool doesn't exist"),
-                                }
-                            }
-                        },
-                        calloop::channel::Event::Closed => {
-                            rt.exit();
-                        }
-                    },
-                )
-                .unwrap();
-            rt.0.data.sender = Some(Rc::new(sender));
-            rt.0.run(event_loop);
+        // starts sketchover, returns when sketchover exits
+        methods.add_method_mut("run", |_, data, ()| {
+            let Some((mut rt, event_loop)) = data.running.take() else {
+                // a reloaded script, we are already running
+                return Ok(());
+            };
+            match ipc::socket_path() {
+                Ok(path) => {
+                    if let Err(e) = ipc::listen(&event_loop.handle(), path) {
+                        log::warn!("Couldn't create the ipc socket: {e}");
+                    }
+                }
+                Err(e) => log::warn!("Couldn't find a path for the ipc socket: {e}"),
+            }
+            rt.run(event_loop);
+            Ok(())
+        });
+    }
+}
+
+fn handle_message(rt: &mut Runtime<LuaBindings>, message: Message) {
+    // the script has moved on, all we can do is to tell the user
+    if let Err(e) = apply_message(rt, message) {
+        log::error!("{e}");
+    }
+}
+
+fn apply_message(rt: &mut Runtime<LuaBindings>, message: Message) -> Result<(), String> {
+    match message {
+        Message::Clear(id) => find_output(rt, id)?.clear(),
+        Message::Quit => rt.exit(),
+        Message::Undo(id) => find_output(rt, id)?.undo(),
+        Message::Unpause(id) => {
+            let idx = rt.locate_output_idx(id).ok_or_else(|| missing_output(id))?;
+            rt.set_pause(false, idx);
+        }
+        Message::Pause(id) => {
+            let idx = rt.locate_output_idx(id).ok_or_else(|| missing_output(id))?;
+            rt.set_pause(true, idx);
+        }
+        Message::SetFg(solid, id) => find_output(rt, id)?.set_fg(solid),
+        Message::Save(id, path) => find_output(rt, id)?
+            .save(&path)
+            .map_err(|e| format!("Couldn't save to {}: {e}", path.display()))?,
+        Message::Restore(id, path) => find_output(rt, id)?
+            .restore(&path)
+            .map_err(|e| format!("Couldn't restore from {}: {e}", path.display()))?,
+        Message::SaveAll => rt
+            .save_all(SAVE_FILE)
+            .map_err(|e| format!("Couldn't save to {SAVE_FILE}: {e}"))?,
+        Message::Passthrough(enable, id) => {
+            let idx = rt.locate_output_idx(id).ok_or_else(|| missing_output(id))?;
+            rt.set_output_passthrough(enable, idx);
+        }
+        Message::Board(id, op) => {
+            let idx = rt.locate_output_idx(id).ok_or_else(|| missing_output(id))?;
+            rt.board(idx, op)?;
+        }
+        Message::SetBackground(id, change) => find_output(rt, id)?.background.apply(&change)?,
+        Message::StopDraw => rt.stop_drawing(),
+        Message::SetStyle(key) => {
+            let lua = rt.data.lua.clone();
+            let res = match rt.active_draw() {
+                Some(draw) => lua
+                    .registry_value::<Table>(&key)
+                    .and_then(|t| update_draw(draw, t)),
+                None => Ok(()),
+            };
+            let _ = lua.remove_registry_value(key);
+            res.map_err(|e| format!("Couldn't set style: {e}"))?;
+        }
+        Message::Drawing(s, pos, draw) => {
+            // tools from lua aren't in the registry, they go away on reload
+            let tool = match rt.tools().create(&s, pos, draw.clone()) {
+                Ok(tool) => tool,
+                Err(_) => lua_tool(&rt.data.lua, s, pos, draw)
+                    .map_err(|e| format!("Couldn't start drawing: {e}"))?,
+            };
+            rt.start_drawing(tool, pos);
+        }
+        Message::ModifierMap(map) => rt.set_modifier_map(map),
+        Message::Snap(snap) => rt.set_snap(snap),
+        Message::ToggleSnap => {
+            let snap = *rt.snap();
+            rt.set_snap(Snap {
+                enabled: !snap.enabled,
+                ..snap
+            });
+        }
+        Message::Reload => reload(rt).map_err(|e| format!("Couldn't reload: {e}"))?,
+    }
+    Ok(())
+}
+
+fn find_output(rt: &mut Runtime<LuaBindings>, id: Option<u32>) -> Result<&mut OutPut, String> {
+    rt.locate_output(id).ok_or_else(|| missing_output(id))
+}
+
+fn missing_output(id: Option<u32>) -> String {
+    match id {
+        Some(id) => format!("There is no output {id}"),
+        None => "There is no current output".to_owned(),
+    }
+}
+
+/// Commands return true, or nil and what went wrong, like the io
+/// functions in lua. Wrap them in assert to make the errors raise.
+type Status = (Option<bool>, Option<String>);
+
+fn status(res: Result<(), String>) -> Status {
+    returned(res.map(|()| true))
+}
+
+/// What a query found, or nil and what went wrong
+fn returned<T>(res: Result<T, String>) -> (Option<T>, Option<String>) {
+    match res {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e)),
+    }
+}
+
+/// Queue a message for the runtime. When we are in a handler the output
+/// is checked right away, so the script gets the error instead of the log.
+fn send(lua: &Lua, sender: &Sender<Message>, id: Option<u32>, message: Message) -> Status {
+    status(check_output(lua, id).and_then(|()| queue(sender, message)))
+}
+
+fn queue(sender: &Sender<Message>, message: Message) -> Result<(), String> {
+    sender
+        .send(message)
+        .map_err(|_| "sketchover has stopped".to_owned())
+}
+
+fn check_output(lua: &Lua, id: Option<u32>) -> Result<(), String> {
+    let Ok(Some(scene)) = lua.named_registry_value::<Option<AnyUserData>>(SCENE_KEY) else {
+        return Ok(());
+    };
+    let Ok(mut rt) = scene.borrow_mut::<Runtime<LuaBindings>>() else {
+        return Ok(());
+    };
+    find_output(&mut rt, id).map(|_| ())
+}
+
+/// A modifier name sets it, false disables the behaviour and nil keeps the
+/// default.
+fn set_modifier(modifier: &mut Option<Modifier>, value: Value) -> mlua::Result<()> {
+    match value {
+        Value::Nil => {}
+        Value::Boolean(false) => *modifier = None,
+        Value::String(s) => {
+            *modifier = Some(s.to_str()?.parse().map_err(Error::RuntimeError)?);
+        }
+        wat => {
+            return Err(Error::RuntimeError(format!(
+                "Expected a modifier or false, got: {}",
+                wat.type_name()
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn pos_table<'lua>(lua: &'lua Lua, pos: (f64, f64)) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("x", pos.0)?;
+    table.set("y", pos.1)?;
+    Ok(table)
+}
+
+const TOOLS_KEY: &str = "sketchover-tools";
+
+/// The tools in the registry of the runtime, the registry is tried first
+/// so a lua tool with the same name would never be drawn
+struct RegistryTools(Vec<String>);
+
+impl RegistryTools {
+    fn new(registry: &ToolRegistry) -> Self {
+        RegistryTools(registry.names().map(str::to_owned).collect())
+    }
+}
+
+/// A tool is a table with a draw function and an optional update
+/// function, both get the state of the tool as the first argument.
+fn register_tool(lua: &Lua, name: String, def: Table) -> mlua::Result<()> {
+    if let Some(tools) = lua.app_data_ref::<RegistryTools>() {
+        if tools.0.contains(&name) {
+            return Err(Error::RuntimeError(format!(
+                "There already is a {name} tool"
+            )));
+        }
+    }
+    def.get::<_, Function>("draw")?;
+    def.get::<_, Option<Function>>("update")?;
+    let tools = match lua.named_registry_value::<Option<Table>>(TOOLS_KEY)? {
+        Some(tools) => tools,
+        None => {
+            let tools = lua.create_table()?;
+            lua.set_named_registry_value(TOOLS_KEY, tools.clone())?;
+            tools
+        }
+    };
+    tools.set(name, def)
+}
+
+fn lua_tool(
+    lua: &Rc<Lua>,
+    name: String,
+    pos: (f64, f64),
+    draw: Draw,
+) -> mlua::Result<Box<dyn Tool>> {
+    let def = match lua.named_registry_value::<Option<Table>>(TOOLS_KEY)? {
+        Some(tools) => tools.get::<_, Option<Table>>(name.as_str())?,
+        None => None,
+    }
+    .ok_or_else(|| Error::RuntimeError(format!("{name} is not a tool")))?;
+    let state = lua.create_table()?;
+    state.set("start", pos_table(lua, pos)?)?;
+    state.set("pos", pos_table(lua, pos)?)?;
+    let script = LuaScript {
+        lua: Rc::downgrade(lua),
+        def: lua.create_registry_value(def)?,
+        state: lua.create_registry_value(state)?,
+    };
+    Ok(Box::new(Scripted::new(name, draw, Box::new(script))))
+}
+
+/// Runs the functions of a tool defined in lua
+struct LuaScript {
+    // the tool stops updating if the script is reloaded. The first lua
+    // state runs the event loop, so it's only marked as Reloaded
+    lua: Weak<Lua>,
+    def: RegistryKey,
+    state: RegistryKey,
+}
+
+impl Script for LuaScript {
+    fn update(&mut self, motion: (f64, f64)) -> Result<Vec<Paint>, Box<dyn std::error::Error>> {
+        let lua = self
+            .lua
+            .upgrade()
+            .filter(|lua| lua.app_data_ref::<Reloaded>().is_none())
+            .ok_or("The script has been reloaded")?;
+        let def: Table = lua.registry_value(&self.def)?;
+        let state: Table = lua.registry_value(&self.state)?;
+        state.set("pos", pos_table(&lua, motion)?)?;
+        if let Some(update) = def.get::<_, Option<Function>>("update")? {
+            update.call::<_, ()>(state.clone())?;
+        }
+        let path = lua.create_userdata(LuaPath::default())?;
+        def.get::<_, Function>("draw")?
+            .call::<_, ()>((state, path.clone()))?;
+        Ok(path.take::<LuaPath>()?.paints)
+    }
+}
+
+/// The path builder passed to the draw function of a tool. stroke and
+/// fill paint the path built so far and start a new one.
+#[derive(Default)]
+struct LuaPath {
+    ops: Vec<PathOp>,
+    paints: Vec<Paint>,
+}
+
+impl UserData for LuaPath {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("move_to", |_, path, (x, y)| {
+            path.ops.push(PathOp::MoveTo(x, y));
+            Ok(())
+        });
+        methods.add_method_mut("line_to", |_, path, (x, y)| {
+            path.ops.push(PathOp::LineTo(x, y));
+            Ok(())
+        });
+        methods.add_method_mut("quad_to", |_, path, (cx, cy, x, y)| {
+            path.ops.push(PathOp::QuadTo(cx, cy, x, y));
+            Ok(())
+        });
+        methods.add_method_mut("cubic_to", |_, path, (cx1, cy1, cx2, cy2, x, y)| {
+            path.ops.push(PathOp::CubicTo(cx1, cy1, cx2, cy2, x, y));
+            Ok(())
+        });
+        methods.add_method_mut("arc", |_, path, (x, y, r, start, sweep)| {
+            path.ops.push(PathOp::Arc(x, y, r, start, sweep));
+            Ok(())
+        });
+        methods.add_method_mut("close", |_, path, ()| {
+            path.ops.push(PathOp::Close);
+            Ok(())
+        });
+        methods.add_method_mut("stroke", |_, path, ()| {
+            let ops = std::mem::take(&mut path.ops);
+            path.paints.push(Paint::Stroke(ops));
+            Ok(())
+        });
+        methods.add_method_mut("fill", |_, path, ()| {
+            let ops = std::mem::take(&mut path.ops);
+            path.paints.push(Paint::Fill(ops));
             Ok(())
         });
     }
@@ -155,19 +549,9 @@ impl UserData for LuaKeyEvent {
     fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
         fields.add_field_method_get("key", |_, event| Ok(event.key.keysym.name()));
         fields.add_field_method_get("modifiers", |lua, event| {
-            let table = lua.create_table()?;
-            table.set("ctrl", event.modifiers.ctrl)?;
-            table.set("alt", event.modifiers.ctrl)?;
-            table.set("shift", event.modifiers.ctrl)?;
-            table.set("caps_lock", event.modifiers.ctrl)?;
-            Ok(table)
-        });
-        fields.add_field_method_get("pos", |lua, event| {
-            let table = lua.create_table()?;
-            table.set("x", event.pos.0)?;
-            table.set("y", event.pos.1)?;
-            Ok(table)
+            modifiers_table(lua, &event.modifiers)
         });
+        fields.add_field_method_get("pos", |lua, event| pos_table(lua, event.pos));
     }
 }
 
@@ -194,32 +578,161 @@ impl UserData for MouseEvent {
             let k = value?;
             Ok(Value::String(k))
         });
-        fields.add_field_method_get("pos", |lua, event| {
-            let table = lua.create_table()?;
-            table.set("x", event.pos.0)?;
-            table.set("y", event.pos.1)?;
-            Ok(table)
+        fields.add_field_method_get("pos", |lua, event| pos_table(lua, event.pos));
+        fields.add_field_method_get("modifiers", |lua, event| {
+            modifiers_table(lua, &event.modifiers)
+        });
+    }
+}
+
+struct ScrollEvent {
+    modifiers: Modifiers,
+    scroll: Mouse,
+    pos: (f64, f64),
+}
+
+impl UserData for ScrollEvent {
+    fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("direction", |_, event| {
+            Ok(match event.scroll {
+                Mouse::ScrollUp => "up",
+                Mouse::ScrollDown => "down",
+                Mouse::ScrollLeft => "left",
+                Mouse::ScrollRight => "right",
+                Mouse::Button(_) => unreachable!("buttons aren't scrolled"),
+            })
         });
+        fields.add_field_method_get("pos", |lua, event| pos_table(lua, event.pos));
         fields.add_field_method_get("modifiers", |lua, event| {
-            let table = lua.create_table()?;
-            table.set("ctrl", event.modifiers.ctrl)?;
-            table.set("alt", event.modifiers.ctrl)?;
-            table.set("shift", event.modifiers.ctrl)?;
-            table.set("caps_lock", event.modifiers.ctrl)?;
-            Ok(table)
+            modifiers_table(lua, &event.modifiers)
+        });
+    }
+}
+
+fn modifiers_table<'lua>(lua: &'lua Lua, modifiers: &Modifiers) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("ctrl", modifiers.ctrl)?;
+    table.set("alt", modifiers.alt)?;
+    table.set("shift", modifiers.shift)?;
+    table.set("caps_lock", modifiers.caps_lock)?;
+    table.set("logo", modifiers.logo)?;
+    Ok(table)
+}
+
+/// The signals scripts can handle
+const SIGNALS: [(Signal, &str); 5] = [
+    (Signal::SIGUSR1, "sigusr1"),
+    (Signal::SIGUSR2, "sigusr2"),
+    (Signal::SIGTSTP, "sigtstp"),
+    (Signal::SIGHUP, "sighup"),
+    (Signal::SIGTERM, "sigterm"),
+];
+
+/// Run the handlers for a signal. Unless a handler returns
+/// sketchover.STOP, the default action runs after them. Returns if it ran.
+fn signal(rt: &mut Runtime<LuaBindings>, signal: Signal) -> bool {
+    let Some((_, name)) = SIGNALS.iter().find(|(s, _)| *s == signal) else {
+        return false;
+    };
+    let cb = Callback {
+        sender: rt.data.sender.clone(),
+        screen_id: rt.current_output_id(),
+    };
+    let handled = with_scene(rt, |lua| {
+        emit_sync_callback(lua, (format!("signal-{name}"), cb)).map(|ret| is_stop(&ret))
+    });
+    match handled {
+        Ok(false) => {}
+        Ok(true) => return false,
+        Err(e) => log::error!("{name} handler failed: {e}"),
+    }
+    match signal {
+        Signal::SIGTERM => rt.exit(),
+        Signal::SIGHUP => {
+            if let Err(e) = reload(rt) {
+                log::error!("Couldn't reload: {e}");
+            }
+        }
+        Signal::SIGTSTP => rt.set_passthrough(!rt.passthrough()),
+        _ => {}
+    }
+    true
+}
+
+/// Returned by after and every, so the timer can be cancelled
+struct LuaTimer {
+    handle: Handle,
+    token: RegistrationToken,
+}
+
+impl UserData for LuaTimer {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("cancel", |_, timer, ()| {
+            timer.handle.remove(timer.token);
+            Ok(())
         });
     }
 }
 
+/// Call func with a callback after ms milliseconds, and then every ms
+/// milliseconds if repeat is set.
+fn add_timer(lua: &Lua, ms: u64, func: Function, repeat: bool) -> mlua::Result<LuaTimer> {
+    // a timer repeating right away would never let the event loop wait
+    if repeat && ms == 0 {
+        return Err(Error::RuntimeError(
+            "A repeating timer needs an interval of at least 1 ms".to_owned(),
+        ));
+    }
+    let handle = lua
+        .app_data_ref::<Handle>()
+        .ok_or_else(|| Error::RuntimeError("No event loop to add the timer to".to_owned()))?
+        .clone();
+    let key = lua.create_registry_value(func)?;
+    let interval = Duration::from_millis(ms);
+    let token = handle
+        .insert_source(Timer::from_duration(interval), move |_, _, rt| {
+            let lua = rt.data.lua.clone();
+            // the timer belongs to a script that has been reloaded
+            let Ok(func) = lua.registry_value::<Function>(&key) else {
+                return TimeoutAction::Drop;
+            };
+            let cb = Callback {
+                sender: rt.data.sender.clone(),
+                screen_id: rt.current_output_id(),
+            };
+            if let Err(e) = with_scene(rt, |_| func.call::<_, ()>(cb)) {
+                log::error!("timer failed: {e}");
+            }
+            if repeat {
+                TimeoutAction::ToDuration(interval)
+            } else {
+                TimeoutAction::Drop
+            }
+        })
+        .map_err(|e| Error::RuntimeError(format!("Couldn't add timer: {e}")))?;
+    Ok(LuaTimer { handle, token })
+}
+
 struct Callback {
-    sender: Rc<SyncSender<Message>>,
+    sender: Rc<Sender<Message>>,
     screen_id: Option<u32>,
 }
 
 impl Callback {
+    /// Send a message that doesn't act on an output
+    fn send(&self, message: Message) -> Status {
+        status(queue(&self.sender, message))
+    }
+
+    /// Send a message for the output with id, None is the current output
+    fn send_to(&self, lua: &Lua, id: Option<u32>, message: Message) -> Status {
+        send(lua, &self.sender, id, message)
+    }
+
     fn screen_id(&self, value: Value) -> mlua::Result<Option<u32>> {
         match value {
             Value::Nil => Ok(None),
+            Value::Integer(n) => Ok(Some(n as u32)),
             Value::Number(n) => Ok(Some(n as u32)),
             wat => Err(Error::RuntimeError(format!(
                 "Expected number or nil, got: {}",
@@ -230,32 +743,80 @@ impl Callback {
 }
 
 impl UserData for Callback {
+    // The output the event happened on. The commands use the current
+    // output when they are applied, unless they are given an id.
+    fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("screen_id", |_, cb| Ok(cb.screen_id));
+    }
+
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_method("quit", |_, cb, ()| {
-            cb.sender.send(Message::Quit).unwrap();
-            Ok(())
+        // The shapes are numbered from 1, oldest first, the same order
+        // as they are drawn and undone in.
+        methods.add_method("shapes", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            let shapes = with_output(lua, id, |rt, idx| {
+                let shapes = lua.create_table()?;
+                for tool in rt.outputs()[idx].draws.iter() {
+                    shapes.push(shape_table(lua, tool.as_ref())?)?;
+                }
+                Ok(Ok(shapes))
+            })?;
+            Ok(returned(shapes))
+        });
+        methods.add_method("remove_shape", |lua, cb, (index, id): (usize, Value)| {
+            let id = cb.screen_id(id)?;
+            let removed = with_output(lua, id, |rt, idx| {
+                Ok(index
+                    .checked_sub(1)
+                    .and_then(|i| rt.remove_shape(idx, i))
+                    .map(|_| ())
+                    .ok_or_else(|| no_shape(index)))
+            })?;
+            Ok(status(removed))
         });
-        methods.add_method("clear", |_, cb, value| {
+        methods.add_method(
+            "restyle",
+            |lua, cb, (index, table, id): (usize, Table, Value)| {
+                let id = cb.screen_id(id)?;
+                update_draw(&mut Draw::default(), table.clone())?;
+                let restyled = with_output(lua, id, |rt, idx| {
+                    match shape(&mut rt.outputs_mut()[idx].draws, index) {
+                        Ok(shape) => update_draw(shape.style_mut(), table).map(Ok),
+                        Err(e) => Ok(Err(e)),
+                    }
+                })?;
+                Ok(status(restyled))
+            },
+        );
+        methods.add_method(
+            "translate",
+            |lua, cb, (index, dx, dy, id): (usize, f64, f64, Value)| {
+                let id = cb.screen_id(id)?;
+                let moved = with_output(lua, id, |rt, idx| {
+                    let shape = shape(&mut rt.outputs_mut()[idx].draws, index);
+                    Ok(shape.map(|shape| shape.translate((dx, dy))))
+                })?;
+                Ok(status(moved))
+            },
+        );
+        methods.add_method("quit", |_, cb, ()| Ok(cb.send(Message::Quit)));
+        methods.add_method("clear", |lua, cb, value| {
             let id = cb.screen_id(value)?;
-            cb.sender.send(Message::Clear(id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::Clear(id)))
         });
-        methods.add_method("undo", |_, cb, value| {
+        methods.add_method("undo", |lua, cb, value| {
             let id = cb.screen_id(value)?;
-            cb.sender.send(Message::Undo(id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::Undo(id)))
         });
-        methods.add_method("pause", |_, cb, value| {
+        methods.add_method("pause", |lua, cb, value| {
             let id = cb.screen_id(value)?;
-            cb.sender.send(Message::Pause(id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::Pause(id)))
         });
-        methods.add_method("unpause", |_, cb, value| {
+        methods.add_method("unpause", |lua, cb, value| {
             let id = cb.screen_id(value)?;
-            cb.sender.send(Message::Unpause(id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::Unpause(id)))
         });
-        methods.add_method("set_fg", |_, cb, (color_value, id)| {
+        methods.add_method("set_fg", |lua, cb, (color_value, id)| {
             let mut color = SolidSource {
                 r: 0,
                 g: 0,
@@ -266,58 +827,192 @@ impl UserData for Callback {
             let id = cb.screen_id(id)?;
             lua_color(&mut color, color_value)?;
 
-            cb.sender.send(Message::SetFg(color, id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::SetFg(color, id)))
         });
 
-        methods.add_method("save", |_, cb, id| {
+        methods.add_method("save", |lua, cb, id| {
             let id = cb.screen_id(id)?;
-            cb.sender.send(Message::Save(id)).unwrap();
-            Ok(())
+            Ok(cb.send_to(lua, id, Message::Save(id, SAVE_FILE.into())))
         });
 
-        methods.add_method("stop_draw", |_, cb, ()| {
-            cb.sender.send(Message::StopDraw).unwrap();
-            Ok(())
+        methods.add_method("save_all", |_, cb, ()| Ok(cb.send(Message::SaveAll)));
+
+        // The names of the boards, in order, and the one that is shown
+        methods.add_method("boards", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            let boards = with_output(lua, id, |rt, idx| {
+                let boards = &rt.outputs()[idx].boards;
+                let names = lua.create_sequence_from(boards.names())?;
+                Ok(Ok((names, boards.name().to_owned())))
+            })?;
+            Ok(match boards {
+                Ok((names, current)) => (Some(names), Some(current)),
+                Err(e) => (None, Some(e)),
+            })
+        });
+        methods.add_method("background", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            let background = with_output(lua, id, |rt, idx| {
+                let background = &rt.outputs()[idx].background;
+                let table = lua.create_table()?;
+                table.set("mode", background.mode.name())?;
+                table.set("spacing", background.spacing)?;
+                table.set("color", color_table(lua, background.color)?)?;
+                Ok(Ok(table))
+            })?;
+            Ok(returned(background))
+        });
+        methods.add_method("set_background", |lua, cb, (value, id): (Value, Value)| {
+            let id = cb.screen_id(id)?;
+            let change = lua_background(value)?;
+            Ok(cb.send_to(lua, id, Message::SetBackground(id, change)))
+        });
+        methods.add_method(
+            "new_board",
+            |lua, cb, (name, id): (Option<String>, Value)| {
+                let id = cb.screen_id(id)?;
+                Ok(cb.send_to(lua, id, Message::Board(id, BoardOp::New(name))))
+            },
+        );
+        methods.add_method("next_board", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            Ok(cb.send_to(lua, id, Message::Board(id, BoardOp::Next)))
+        });
+        methods.add_method("prev_board", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            Ok(cb.send_to(lua, id, Message::Board(id, BoardOp::Prev)))
+        });
+        methods.add_method("delete_board", |lua, cb, id| {
+            let id = cb.screen_id(id)?;
+            Ok(cb.send_to(lua, id, Message::Board(id, BoardOp::Delete)))
+        });
+        methods.add_method("select_board", |lua, cb, (name, id): (String, Value)| {
+            let id = cb.screen_id(id)?;
+            Ok(cb.send_to(lua, id, Message::Board(id, BoardOp::Select(name))))
+        });
+
+        // run the script again, the drawings are kept
+        methods.add_method("reload", |_, cb, ()| Ok(cb.send(Message::Reload)));
+
+        methods.add_method("stop_draw", |_, cb, ()| Ok(cb.send(Message::StopDraw)));
+
+        methods.add_method("toggle_snap", |_, cb, ()| Ok(cb.send(Message::ToggleSnap)));
+
+        methods.add_method("set_style", |lua, cb, table: Table| {
+            // catch errors here, where the script can see them
+            update_draw(&mut Draw::default(), table.clone())?;
+            let key = lua.create_registry_value(table)?;
+            Ok(cb.send(Message::SetStyle(key)))
         });
 
         methods.add_method(
             "draw",
-            |_, cb, (name, table, draw): (String, Table, Table)| {
+            |lua, cb, (name, table, draw): (String, Table, Table)| {
                 let x = table.get("x")?;
                 let y = table.get("y")?;
                 let draw = table_to_draw(draw)?;
-                cb.sender
-                    .send(Message::Drawing(name, (x, y), draw))
-                    .unwrap();
-                Ok(())
+                Ok(cb.send_to(lua, None, Message::Drawing(name, (x, y), draw)))
             },
         );
     }
 }
 
-struct LuaOutPut(OutputInfo);
+const SCENE_KEY: &str = "sketchover-scene";
+
+/// Run f with the runtime reachable from lua, so the callbacks can read
+/// and change the shapes while the handlers run. The shapes can't be
+/// reached outside of a handler, since the runtime is only borrowed.
+fn with_scene<R>(
+    rt: &mut Runtime<LuaBindings>,
+    f: impl FnOnce(&Lua) -> mlua::Result<R>,
+) -> mlua::Result<R> {
+    let lua = rt.data.lua.clone();
+    lua.scope(|scope| {
+        let scene = scope.create_any_userdata_ref_mut(rt)?;
+        lua.set_named_registry_value(SCENE_KEY, scene)?;
+        let res = f(&lua);
+        lua.unset_named_registry_value(SCENE_KEY)?;
+        res
+    })
+}
+
+/// Run f with the runtime and the index of the output with id. Like the
+/// commands, what went wrong is returned and bad arguments are raised.
+fn with_output<R>(
+    lua: &Lua,
+    id: Option<u32>,
+    f: impl FnOnce(&mut Runtime<LuaBindings>, usize) -> mlua::Result<Result<R, String>>,
+) -> mlua::Result<Result<R, String>> {
+    let Some(scene) = lua.named_registry_value::<Option<AnyUserData>>(SCENE_KEY)? else {
+        return Ok(Err("Shapes can only be used in a handler".to_owned()));
+    };
+    let mut rt = scene.borrow_mut::<Runtime<LuaBindings>>()?;
+    match rt.locate_output_idx(id) {
+        Some(idx) => f(&mut rt, idx),
+        None => Ok(Err(missing_output(id))),
+    }
+}
+
+fn shape(draws: &mut [Box<dyn Tool>], index: usize) -> Result<&mut Box<dyn Tool>, String> {
+    index
+        .checked_sub(1)
+        .and_then(|i| draws.get_mut(i))
+        .ok_or_else(|| no_shape(index))
+}
+
+fn no_shape(index: usize) -> String {
+    format!("There is no shape {index}")
+}
+
+fn shape_table<'lua>(lua: &'lua Lua, tool: &dyn Tool) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("kind", tool.name())?;
+    // seconds since the shape was started
+    table.set("age", tool.created().elapsed().as_secs_f64())?;
+    if let Some(bounds) = tool.bounds() {
+        let b = lua.create_table()?;
+        b.set("x", bounds.x)?;
+        b.set("y", bounds.y)?;
+        b.set("width", bounds.width)?;
+        b.set("height", bounds.height)?;
+        table.set("bounds", b)?;
+    }
+    table.set("style", draw_to_table(lua, tool.style())?)?;
+    Ok(table)
+}
+
+/// An output, the fields are read from the runtime so they are always up
+/// to date, and the methods are sent to the output with the same id.
+struct LuaOutPut {
+    id: u32,
+    /// The info the output was created with. The runtime only has the
+    /// output after its new_output handlers, and not after it's gone.
+    created: Option<OutputInfo>,
+    sender: Rc<Sender<Message>>,
+}
 
 impl UserData for LuaOutPut {
     fn add_fields<'lua, F: mlua::prelude::LuaUserDataFields<'lua, Self>>(fields: &mut F) {
-        fields.add_field_method_get("id", |_, info| Ok(info.0.id));
-        fields.add_field_method_get("name", |_, info| Ok(info.0.name.clone()));
-        fields.add_field_method_get("model", |_, info| Ok(info.0.model.clone()));
-        fields.add_field_method_get("make", |_, info| Ok(info.0.make.clone()));
-        fields.add_field_method_get("location", |lua, info| {
+        fields.add_field_method_get("id", |_, output| Ok(output.id));
+        fields.add_field_method_get("name", |lua, output| Ok(output.info(lua)?.name));
+        fields.add_field_method_get("model", |lua, output| Ok(output.info(lua)?.model));
+        fields.add_field_method_get("make", |lua, output| Ok(output.info(lua)?.make));
+        fields.add_field_method_get("location", |lua, output| {
+            let info = output.info(lua)?;
             let table = lua.create_table()?;
-            table.set(1, info.0.location.0)?;
-            table.set(2, info.0.location.1)?;
+            table.set(1, info.location.0)?;
+            table.set(2, info.location.1)?;
             Ok(table)
         });
-        fields.add_field_method_get("physical_size", |lua, info| {
+        fields.add_field_method_get("physical_size", |lua, output| {
+            let info = output.info(lua)?;
             let table = lua.create_table()?;
-            table.set(1, info.0.physical_size.0)?;
-            table.set(2, info.0.physical_size.1)?;
+            table.set(1, info.physical_size.0)?;
+            table.set(2, info.physical_size.1)?;
             Ok(table)
         });
-        fields.add_field_method_get("subpixel", |_, info| {
-            let str = match info.0.subpixel {
+        fields.add_field_method_get("subpixel", |lua, output| {
+            let str = match output.info(lua)?.subpixel {
                 Subpixel::Unknown => "unknown",
                 Subpixel::None => "none",
                 Subpixel::HorizontalRgb => "HorizontalRgb",
@@ -328,8 +1023,8 @@ impl UserData for LuaOutPut {
             };
             Ok(str.to_owned())
         });
-        fields.add_field_method_get("transform", |_, info| {
-            let str = match info.0.transform {
+        fields.add_field_method_get("transform", |lua, output| {
+            let str = match output.info(lua)?.transform {
                 Transform::Normal => "normal",
                 Transform::_90 => "90",
                 Transform::_180 => "180",
@@ -342,10 +1037,12 @@ impl UserData for LuaOutPut {
             };
             Ok(str.to_owned())
         });
-        fields.add_field_method_get("scale_facor", |_, info| Ok(info.0.scale_factor));
-        // fields.add_field_method_get("modes", |_, info| Ok(info.0.make.clone()));
-        fields.add_field_method_get("logical_position", |lua, info| {
-            match info.0.logical_position {
+        fields.add_field_method_get("scale_facor", |lua, output| {
+            Ok(output.info(lua)?.scale_factor)
+        });
+        // fields.add_field_method_get("modes", |_, output| Ok(output.info.make.clone()));
+        fields.add_field_method_get("logical_position", |lua, output| {
+            match output.info(lua)?.logical_position {
                 None => Ok(Value::Nil),
                 Some(p) => {
                     let table = lua.create_table()?;
@@ -355,61 +1052,150 @@ impl UserData for LuaOutPut {
                 }
             }
         });
-        fields.add_field_method_get("logical_size", |lua, info| match info.0.logical_size {
-            None => Ok(Value::Nil),
-            Some(p) => {
-                let table = lua.create_table()?;
-                table.set(1, p.0)?;
-                table.set(2, p.1)?;
-                Ok(Value::Table(table))
+        fields.add_field_method_get("logical_size", |lua, output| {
+            match output.info(lua)?.logical_size {
+                None => Ok(Value::Nil),
+                Some(p) => {
+                    let table = lua.create_table()?;
+                    table.set(1, p.0)?;
+                    table.set(2, p.1)?;
+                    Ok(Value::Table(table))
+                }
             }
         });
-        fields.add_field_method_get("description", |_, info| Ok(info.0.description.clone()));
+        fields.add_field_method_get("description", |lua, output| {
+            Ok(output.info(lua)?.description)
+        });
     }
-}
-
-impl Events for LuaBindings {
-    fn init(r: &mut Runtime<Self>) {
-        let lua = &r.data.lua.clone();
-        let id = r.current_output_id();
 
-        let cb = Callback {
-            sender: r.data.sender.as_ref().unwrap().clone(),
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("save", |lua, output, path: Option<String>| {
+            let path = PathBuf::from(path.as_deref().unwrap_or(SAVE_FILE));
+            Ok(output.send(lua, |id| Message::Save(id, path)))
+        });
+        // replace the drawings with the ones saved for this output
+        methods.add_method("restore", |lua, output, path: Option<String>| {
+            let path = PathBuf::from(path.as_deref().unwrap_or(SAVE_FILE));
+            Ok(output.send(lua, |id| Message::Restore(id, path)))
+        });
+        methods.add_method("set_background", |lua, output, value: Value| {
+            let change = lua_background(value)?;
+            Ok(output.send(lua, |id| Message::SetBackground(id, change)))
+        });
+        methods.add_method("new_board", |lua, output, name: Option<String>| {
+            Ok(output.send(lua, |id| Message::Board(id, BoardOp::New(name))))
+        });
+        methods.add_method("next_board", |lua, output, ()| {
+            Ok(output.send(lua, |id| Message::Board(id, BoardOp::Next)))
+        });
+        methods.add_method("prev_board", |lua, output, ()| {
+            Ok(output.send(lua, |id| Message::Board(id, BoardOp::Prev)))
+        });
+        methods.add_method("delete_board", |lua, output, ()| {
+            Ok(output.send(lua, |id| Message::Board(id, BoardOp::Delete)))
+        });
+        methods.add_method("select_board", |lua, output, name: String| {
+            Ok(output.send(lua, |id| Message::Board(id, BoardOp::Select(name))))
+        });
+        methods.add_method("pause", |lua, output, ()| {
+            Ok(output.send(lua, Message::Pause))
+        });
+        methods.add_method("unpause", |lua, output, ()| {
+            Ok(output.send(lua, Message::Unpause))
+        });
+        methods.add_method("clear", |lua, output, ()| {
+            Ok(output.send(lua, Message::Clear))
+        });
+        methods.add_method(
+            "undo",
+            |lua, output, ()| Ok(output.send(lua, Message::Undo)),
+        );
+        methods.add_method("set_fg", |lua, output, value: Value| {
+            let mut color = SolidSource {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            };
+            lua_color(&mut color, value)?;
+            Ok(output.send(lua, |id| Message::SetFg(color, id)))
+        });
+        // a disabled output lets the input through to the windows below
+        methods.add_method("enable", |lua, output, ()| {
+            Ok(output.send(lua, |id| Message::Passthrough(false, id)))
+        });
+        methods.add_method("disable", |lua, output, ()| {
+            Ok(output.send(lua, |id| Message::Passthrough(true, id)))
+        });
+    }
+}
+
+impl LuaOutPut {
+    fn new(info: OutputInfo, sender: Rc<Sender<Message>>) -> Self {
+        LuaOutPut {
+            id: info.id,
+            created: Some(info),
+            sender,
+        }
+    }
+
+    /// The info of the output as the runtime has it now, or as it was
+    /// created when the runtime can't be reached
+    fn info(&self, lua: &Lua) -> mlua::Result<OutputInfo> {
+        let live = lua
+            .named_registry_value::<Option<AnyUserData>>(SCENE_KEY)?
+            .and_then(|scene| {
+                let rt = scene.borrow::<Runtime<LuaBindings>>().ok()?;
+                let output = rt.outputs().iter().find(|o| o.info.id == self.id)?;
+                Some(output.info.clone())
+            });
+        live.or_else(|| self.created.clone())
+            .ok_or_else(|| Error::RuntimeError(missing_output(Some(self.id))))
+    }
+
+    fn send(&self, lua: &Lua, message: impl FnOnce(Option<u32>) -> Message) -> Status {
+        let id = Some(self.id);
+        send(lua, &self.sender, id, message(id))
+    }
+}
+
+impl Events for LuaBindings {
+    fn init(r: &mut Runtime<Self>) {
+        let id = r.current_output_id();
+
+        let cb = Callback {
+            sender: r.data.sender.clone(),
             screen_id: id,
         };
 
-        emit_sync_callback(lua, ("init".to_owned(), cb)).expect("callback failed");
+        emit_event(r, "init", cb);
     }
 
     fn destroy_output(r: &mut Runtime<Self>, output_id: u32) {
-        let lua = &r.data.lua.clone();
         let id = r.current_output_id();
 
         let cb = Callback {
-            sender: r.data.sender.as_ref().unwrap().clone(),
+            sender: r.data.sender.clone(),
             screen_id: id,
         };
 
-        emit_sync_callback(lua, ("destroy_output".to_owned(), (cb, output_id)))
-            .expect("callback failed");
+        emit_event(r, "destroy_output", (cb, output_id));
     }
 
     fn new_output(r: &mut Runtime<Self>, output: &mut OutPut) {
-        let lua = &r.data.lua.clone();
         let id = r.current_output_id();
 
         let cb = Callback {
-            sender: r.data.sender.as_ref().unwrap().clone(),
+            sender: r.data.sender.clone(),
             screen_id: id,
         };
 
-        let args = LuaOutPut(output.info.clone());
+        let args = LuaOutPut::new(output.info.clone(), r.data.sender.clone());
 
-        emit_sync_callback(lua, ("new_output".to_owned(), (cb, args))).expect("callback failed");
+        emit_event(r, "new_output", (cb, args));
     }
 
     fn keybinding(r: &mut Runtime<Self>, event: KeyEvent, press: bool) {
-        let lua = &r.data.lua.clone();
         let modifiers = r.modifiers();
         let pos = r.pos();
         let id = r.current_output_id();
@@ -421,15 +1207,13 @@ impl Events for LuaBindings {
         };
 
         let cb = Callback {
-            sender: r.data.sender.as_ref().unwrap().clone(),
+            sender: r.data.sender.clone(),
             screen_id: id,
         };
 
-        emit_sync_callback(lua, ("keypress".to_owned(), (cb, args, press)))
-            .expect("callback failed");
+        emit_event(r, "keypress", (cb, args, press));
     }
     fn mousebinding(r: &mut Runtime<Self>, button: u32, press: bool) {
-        let lua = &r.data.lua.clone();
         let modifiers = r.modifiers();
         let pos = r.pos();
         let id = r.current_output_id();
@@ -440,24 +1224,147 @@ impl Events for LuaBindings {
             pos,
         };
         let cb = Callback {
-            sender: r.data.sender.as_ref().unwrap().clone(),
+            sender: r.data.sender.clone(),
             screen_id: id,
         };
 
         // r.start_drawing(Box::new(Pen::new()));
-        emit_sync_callback(lua, ("mousepress".to_owned(), (cb, args, press)))
-            .expect("callback failed");
+        emit_event(r, "mousepress", (cb, args, press));
+    }
+
+    fn scrollbinding(r: &mut Runtime<Self>, scroll: MouseMap) {
+        let pos = r.pos();
+        let id = r.current_output_id();
+
+        let args = ScrollEvent {
+            modifiers: scroll.modifier,
+            scroll: scroll.event,
+            pos,
+        };
+        let cb = Callback {
+            sender: r.data.sender.clone(),
+            screen_id: id,
+        };
+
+        emit_event(r, "scroll", (cb, args));
+    }
+
+    fn shutdown(r: &mut Runtime<Self>) {
+        let cb = Callback {
+            sender: r.data.sender.clone(),
+            screen_id: r.current_output_id(),
+        };
+        emit_event(r, "shutdown", cb);
+    }
+
+    fn request(r: &mut Runtime<Self>, request: &Request) -> Response {
+        if *request == Request::Reload {
+            return match reload(r) {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::error(format!("Couldn't reload: {e}")),
+            };
+        }
+        let id = r.current_output_id();
+
+        let cb = Callback {
+            sender: r.data.sender.clone(),
+            screen_id: id,
+        };
+
+        let res = with_scene(r, |lua| {
+            let table = request_to_table(lua, request)?;
+            // a handler that returns false or a string refuses the request,
+            // like STOP the handlers after it aren't called
+            let stop = |ret: &Value| {
+                is_stop(ret) || matches!(ret, Value::Boolean(false) | Value::String(_))
+            };
+            let res = match emit_until(lua, ("ipc".to_owned(), (cb, table)), stop)? {
+                Value::Boolean(false) => Response::error("Request refused by config"),
+                Value::String(s) => Response::error(s.to_string_lossy()),
+                _ => Response::Ok,
+            };
+            Ok(res)
+        });
+        res.unwrap_or_else(|e| Response::error(e.to_string()))
+    }
+}
+
+fn request_to_table<'lua>(lua: &'lua Lua, request: &Request) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    match request {
+        Request::SetColor { color } => {
+            table.set("command", "set_color")?;
+            let hex = HexColor::parse(color).map_err(Error::external)?;
+            let color = lua.create_table()?;
+            color.set("r", hex.r)?;
+            color.set("g", hex.g)?;
+            color.set("b", hex.b)?;
+            color.set("a", hex.a)?;
+            table.set("color", color)?;
+        }
+        Request::SetTool { tool } => {
+            table.set("command", "set_tool")?;
+            table.set("tool", tool.as_str())?;
+        }
+        request => {
+            return Err(Error::RuntimeError(format!(
+                "{request:?} can't be passed to lua"
+            )))
+        }
     }
+    Ok(table)
 }
 pub fn table_to_draw(table: Table) -> mlua::Result<Draw> {
     let mut default = Draw::default();
+    update_draw(&mut default, table)?;
+    Ok(default)
+}
+
+/// The color as a table that lua_color reads back
+fn color_table(lua: &Lua, color: SolidSource) -> mlua::Result<Table<'_>> {
+    let table = lua.create_table()?;
+    table.set("r", color.r)?;
+    table.set("g", color.g)?;
+    table.set("b", color.b)?;
+    table.set("a", color.a)?;
+    Ok(table)
+}
+
+/// The draw as a table that table_to_draw reads back
+pub fn draw_to_table<'lua>(lua: &'lua Lua, draw: &Draw) -> mlua::Result<Table<'lua>> {
+    let color = color_table(lua, draw.color)?;
+    let style = lua.create_table()?;
+    style.set("width", draw.style.width)?;
+    let cap = match draw.style.cap {
+        LineCap::Round => "round",
+        LineCap::Square => "square",
+        LineCap::Butt => "butt",
+    };
+    style.set("cap", cap)?;
+    let join = match draw.style.join {
+        LineJoin::Round => "round",
+        LineJoin::Miter => "miter",
+        LineJoin::Bevel => "bevel",
+    };
+    style.set("join", join)?;
+    style.set("miter_limit", draw.style.miter_limit)?;
+    style.set("dash_array", draw.style.dash_array.clone())?;
+    style.set("dash_offset", draw.style.dash_offset)?;
+    let table = lua.create_table()?;
+    table.set("color", color)?;
+    table.set("style", style)?;
+    Ok(table)
+}
+
+/// Set the fields in the table, everything else is left as is
+pub fn update_draw(draw: &mut Draw, table: Table) -> mlua::Result<()> {
     if let Ok(v) = table.get("style") {
-        lua_style(&mut default.style, v)?;
+        lua_style(&mut draw.style, v)?;
     }
     if let Ok(v) = table.get("color") {
-        lua_color(&mut default.color, v)?;
+        lua_color(&mut draw.color, v)?;
     }
-    Ok(default)
+    Ok(())
 }
 
 pub fn lua_style(style: &mut StrokeStyle, value: Value) -> mlua::Result<()> {
@@ -503,41 +1410,160 @@ pub fn lua_color(color: &mut SolidSource, value: Value) -> mlua::Result<()> {
     }
 }
 
+/// A background change from lua, either the name of a mode or a table
+/// with any of mode, spacing and color
+fn lua_background(value: Value) -> mlua::Result<Change> {
+    let mode = |name: &str| {
+        Mode::from_name(name)
+            .ok_or_else(|| Error::RuntimeError(format!("{name} is not a background")))
+    };
+    match value {
+        Value::String(name) => Ok(Change {
+            mode: Some(mode(name.to_str()?)?),
+            ..Default::default()
+        }),
+        Value::Table(t) => {
+            let color = match t.get::<_, Value>("color")? {
+                Value::Nil => None,
+                value => {
+                    let mut color = SolidSource {
+                        r: 0,
+                        g: 0,
+                        b: 0,
+                        a: 0xff,
+                    };
+                    lua_color(&mut color, value)?;
+                    Some(color)
+                }
+            };
+            Ok(Change {
+                mode: t
+                    .get::<_, Option<String>>("mode")?
+                    .as_deref()
+                    .map(mode)
+                    .transpose()?,
+                spacing: t
+                    .get::<_, Option<f32>>("spacing")?
+                    .map(check_spacing)
+                    .transpose()
+                    .map_err(Error::RuntimeError)?,
+                color,
+            })
+        }
+        wat => Err(Error::RuntimeError(format!(
+            "Expected string or table, got: {}",
+            wat.type_name()
+        ))),
+    }
+}
+
+/// Emit an event where the handlers don't return anything. Errors are
+/// logged, a broken handler shouldn't take sketchover down.
+fn emit_event<A>(rt: &mut Runtime<LuaBindings>, name: &str, args: A)
+where
+    A: for<'lua> IntoLuaMulti<'lua>,
+{
+    let res = with_scene(rt, |lua| {
+        emit_sync_callback(lua, (name.to_owned(), args)).map(|_| ())
+    });
+    if let Err(e) = res {
+        log::error!("{name} handler failed: {e}");
+    }
+}
+
+// Returned by a handler to stop the handlers after it, any other value
+// is ignored, so `return cb:save()` doesn't stop anything
+static STOP: u8 = 0;
+
+fn stop_value() -> LightUserData {
+    LightUserData(&STOP as *const u8 as *mut c_void)
+}
+
+fn is_stop(value: &Value) -> bool {
+    matches!(value, Value::LightUserData(ud) if *ud == stop_value())
+}
+
+/// Call the handlers for an event in order. A handler that returns
+/// sketchover.STOP stops the handlers after it from being called and
+/// STOP is the result, otherwise it's nil.
 pub fn emit_sync_callback<'lua, A>(
+    lua: &'lua Lua,
+    event: (String, A),
+) -> mlua::Result<mlua::Value<'lua>>
+where
+    A: IntoLuaMulti<'lua>,
+{
+    emit_until(lua, event, is_stop)
+}
+
+/// Call the handlers for an event in order, until one returns a value
+/// stop is true for. That value is the result, nil if none did.
+fn emit_until<'lua, A>(
     lua: &'lua Lua,
     (name, args): (String, A),
+    stop: impl Fn(&Value) -> bool,
 ) -> mlua::Result<mlua::Value<'lua>>
 where
     A: IntoLuaMulti<'lua>,
 {
-    let decorated_name = format!("sketchover-event-{}", name);
-    let tbl: mlua::Value = lua.named_registry_value(&decorated_name)?;
-    match tbl {
-        Value::Table(tbl) => {
-            if let Some(func) = tbl.sequence_values::<mlua::Function>().next() {
-                let func = func?;
-                return func.call(args);
-            }
-            Ok(mlua::Value::Nil)
+    let tbl: Option<Table> = lua.named_registry_value(&event_key(&name))?;
+    let Some(tbl) = tbl else {
+        return Ok(Value::Nil);
+    };
+    // handlers can unregister themselves while we are iterating
+    let handlers = tbl
+        .sequence_values::<Function>()
+        .collect::<mlua::Result<Vec<_>>>()?;
+    let args = args.into_lua_multi(lua)?;
+    for func in handlers {
+        let ret: Value = func.call(args.clone())?;
+        if stop(&ret) {
+            return Ok(ret);
         }
-        _ => Ok(mlua::Value::Nil),
+    }
+    Ok(Value::Nil)
+}
+
+fn event_key(name: &str) -> String {
+    format!("sketchover-event-{}", name)
+}
+
+/// Returned when registering a handler, so it can be unregistered later
+pub struct Handler {
+    event: String,
+    func: RegistryKey,
+}
+
+impl UserData for Handler {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // returns false if the handler was already unregistered
+        methods.add_method("unregister", |lua, handler, ()| {
+            let func: Function = lua.registry_value(&handler.func)?;
+            unregister_event(lua, &handler.event, func)
+        });
     }
 }
 
-pub fn register_event(lua: &Lua, (name, func): (String, mlua::Function)) -> mlua::Result<()> {
-    let register_name = format!("sketchover-event-{}", name);
+/// Handlers are kept in a list per event and are called in the order
+/// they were registered.
+pub fn register_event(lua: &Lua, (name, func): (String, mlua::Function)) -> mlua::Result<Handler> {
+    let register_name = event_key(&name);
     let tbl: mlua::Value = lua.named_registry_value(&register_name)?;
+    let handler = Handler {
+        func: lua.create_registry_value(func.clone())?,
+        event: name,
+    };
     match tbl {
         mlua::Value::Nil => {
             let tbl = lua.create_table()?;
             tbl.set(1, func)?;
             lua.set_named_registry_value(&register_name, tbl)?;
-            Ok(())
+            Ok(handler)
         }
         mlua::Value::Table(tbl) => {
             let len = tbl.raw_len();
             tbl.set(len + 1, func)?;
-            Ok(())
+            Ok(handler)
         }
         _ => Err(mlua::Error::external(anyhow::anyhow!(
             "registry key for {} has invalid type",
@@ -546,29 +1572,35 @@ pub fn register_event(lua: &Lua, (name, func): (String, mlua::Function)) -> mlua
     }
 }
 
-pub fn get_or_create_runtime(lua: Rc<Lua>, name: &str) -> anyhow::Result<()> {
-    let globals = lua.globals();
-    let package: Table = globals.get("package")?;
-    let loaded: Table = package.get("loaded")?;
-
-    let module = loaded.get(name)?;
-    match module {
-        Value::Nil => {
-            let binds = LuaBindings::new(lua.clone());
-
-            let rt = Runtime::init(binds);
-            let data = RuntimeData(rt);
-            loaded.set(name, data)?;
-            Ok(())
+fn unregister_event(lua: &Lua, name: &str, func: mlua::Function) -> mlua::Result<bool> {
+    let tbl: Option<Table> = lua.named_registry_value(&event_key(name))?;
+    let Some(tbl) = tbl else {
+        return Ok(false);
+    };
+    for (i, f) in tbl.clone().sequence_values::<Function>().enumerate() {
+        if f? == func {
+            // shifts the handlers after it down, keeping the order
+            tbl.raw_remove(i + 1)?;
+            return Ok(true);
         }
-        Value::UserData(_) => Ok(()),
-        wat => anyhow::bail!(
-            "cannot register module {} as package.loaded.{} is already set to a value of type {}",
-            name,
-            name,
-            wat.type_name()
-        ),
     }
+    Ok(false)
+}
+
+/// Put the module in package.loaded, so require("sketchover") finds it
+fn register_module(
+    lua: &Lua,
+    module: RuntimeData,
+    handle: Handle,
+    tools: RegistryTools,
+) -> anyhow::Result<()> {
+    // for the functions that need the event loop, like timers
+    lua.set_app_data(handle);
+    lua.set_app_data(tools);
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    loaded.set("sketchover", module)?;
+    Ok(())
 }
 
 fn read_linecap(m: &str) -> mlua::Result<LineCap> {
@@ -590,8 +1622,10 @@ fn read_linecap(m: &str) -> mlua::Result<LineCap> {
 fn read_linejoin(m: &str) -> mlua::Result<LineJoin> {
     let res = match m {
         "round" => LineJoin::Round,
-        "square" => LineJoin::Miter,
-        "butt" => LineJoin::Bevel,
+        // square and butt are the old names, from before the names matched
+        // the ones in save files
+        "miter" | "square" => LineJoin::Miter,
+        "bevel" | "butt" => LineJoin::Bevel,
         v => {
             return Err(Error::FromLuaConversionError {
                 from: "string",
@@ -603,24 +1637,974 @@ fn read_linejoin(m: &str) -> mlua::Result<LineJoin> {
     Ok(res)
 }
 
-pub fn make_lua_context(config_file: &Path) -> anyhow::Result<()> {
+/// Used when there is no init.lua, so sketchover does something useful
+/// out of the box.
+const DEFAULT_SCRIPT: &str = include_str!("../../example.lua");
+
+/// How often the script is checked for changes with --watch
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Run the script, or the default script if there is none. The script
+/// calls sketchover:run(), so this returns when sketchover exits.
+pub fn make_lua_context(script: Option<&Path>, watch: bool, repl: bool) -> anyhow::Result<()> {
+    let event_loop = EventLoop::try_new()?;
+    let (sender, receiver) = calloop::channel::channel::<Message>();
+    event_loop
+        .handle()
+        .insert_source(receiver, |event, _, rt| match event {
+            calloop::channel::Event::Msg(m) => handle_message(rt, m),
+            calloop::channel::Event::Closed => rt.exit(),
+        })
+        .map_err(|e| anyhow::anyhow!("Couldn't listen for messages: {e}"))?;
+    let signals = SIGNALS.map(|(signal, _)| signal);
+    event_loop
+        .handle()
+        .insert_source(Signals::new(&signals)?, |event, _, rt| {
+            signal(rt, event.signal());
+        })
+        .map_err(|e| anyhow::anyhow!("Couldn't listen for signals: {e}"))?;
+    if let Some(script) = script.filter(|_| watch) {
+        watch_script(&event_loop.handle(), script.to_path_buf())?;
+    }
+    if repl {
+        // like the control socket, sketchover is still useful without it
+        match ipc::repl_socket_path() {
+            Ok(path) => {
+                if let Err(e) = ipc::listen_repl(&event_loop.handle(), path, eval) {
+                    log::warn!("Couldn't open the repl socket: {e}");
+                }
+            }
+            Err(e) => log::warn!("Couldn't find a place for the repl socket: {e}"),
+        }
+    }
+
     let lua = Rc::new(Lua::new());
+    let sender = Rc::new(sender);
+    let handle = event_loop.handle();
+    let mut rt = Runtime::init(LuaBindings {
+        lua: lua.clone(),
+        sender: sender.clone(),
+        handle: handle.clone(),
+        script: script.map(Path::to_path_buf),
+    });
+    match plugin::plugin_dir() {
+        Ok(dir) => rt.load_plugins(&dir),
+        Err(e) => log::warn!("Couldn't find the plugin dir: {e}"),
+    }
+    let tools = RegistryTools::new(rt.tools());
+    register_module(
+        &lua,
+        RuntimeData {
+            sender,
+            running: Some((rt, event_loop)),
+        },
+        handle,
+        tools,
+    )?;
+    run_script(&lua, script)
+}
 
-    get_or_create_runtime(lua.clone(), "sketchover")?;
-    let path = format!(
-        "package.path = package.path .. ';{}?.lua;'",
-        config_file.to_str().unwrap()
-    );
-    lua.load(&path).exec().unwrap();
-    lua.load("require('mmm')").exec().unwrap();
+fn run_script(lua: &Lua, script: Option<&Path>) -> anyhow::Result<()> {
+    let Some(script) = script else {
+        return lua
+            .load(DEFAULT_SCRIPT)
+            .set_name("=default")
+            .exec()
+            .map_err(|e| anyhow::anyhow!("{e}"));
+    };
 
-    Ok(())
+    let source = std::fs::read_to_string(script)
+        .map_err(|e| anyhow::anyhow!("Couldn't read {}: {e}", script.display()))?;
+    // so the script can require modules next to it
+    if let Some(dir) = script.parent() {
+        let package: Table = lua.globals().get("package")?;
+        let path: String = package.get("path")?;
+        let dir = dir.display();
+        package.set("path", format!("{dir}/?.lua;{dir}/?/init.lua;{path}"))?;
+    }
+    // the error from mlua includes the lua traceback
+    lua.load(&source)
+        .set_name(format!("@{}", script.display()))
+        .exec()
+        .map_err(|e| anyhow::anyhow!("{e}"))
 }
 
-fn main() -> anyhow::Result<()> {
-    if let Ok(xdg_dirs) = BaseDirectories::with_prefix("sketchover") {
-        let config = xdg_dirs.get_config_home();
-        make_lua_context(config.as_path())?;
+/// Set on a lua state that has been replaced by a reload
+struct Reloaded;
+
+/// Run the script again in a new lua state, the drawings are kept. If
+/// the script fails we keep running the old one.
+fn reload(rt: &mut Runtime<LuaBindings>) -> anyhow::Result<()> {
+    let lua = Rc::new(Lua::new());
+    register_module(
+        &lua,
+        RuntimeData {
+            sender: rt.data.sender.clone(),
+            running: None,
+        },
+        rt.data.handle.clone(),
+        RegistryTools::new(rt.tools()),
+    )?;
+    run_script(&lua, rt.data.script.as_deref())?;
+    rt.data.lua.set_app_data(Reloaded);
+    rt.data.lua = lua;
+    // settings the new script doesn't set goes back to the default, what
+    // it sets is queued and applied after this
+    rt.set_modifier_map(ModifierMap::default());
+    rt.set_snap(Snap {
+        enabled: rt.snap().enabled,
+        ..Default::default()
+    });
+
+    // the new script doesn't know about the outputs yet
+    let outputs: Vec<_> = rt.outputs().iter().map(|o| o.info.clone()).collect();
+    for info in outputs {
+        let cb = Callback {
+            sender: rt.data.sender.clone(),
+            screen_id: rt.current_output_id(),
+        };
+        let output = LuaOutPut::new(info, rt.data.sender.clone());
+        emit_event(rt, "new_output", (cb, output));
     }
     Ok(())
 }
+
+/// Reload when the script changes. Editors often replace the file, so we
+/// check the modification time instead of watching the inode.
+fn watch_script(
+    handle: &LoopHandle<'static, Runtime<LuaBindings>>,
+    script: PathBuf,
+) -> anyhow::Result<()> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last = modified(&script);
+    handle
+        .insert_source(Timer::from_duration(WATCH_INTERVAL), move |_, _, rt| {
+            let now = modified(&script);
+            if now.is_some() && now != last {
+                last = now;
+                if let Err(e) = reload(rt) {
+                    log::error!("Couldn't reload {}: {e}", script.display());
+                }
+            }
+            TimeoutAction::ToDuration(WATCH_INTERVAL)
+        })
+        .map_err(|e| anyhow::anyhow!("Couldn't watch the script: {e}"))?;
+    Ok(())
+}
+
+/// Evaluate a chunk from the repl in the live lua state. Like the lua
+/// repl an expression is returned, and `cb` is a callback for the current
+/// output.
+fn eval(rt: &mut Runtime<LuaBindings>, code: &str) -> Eval {
+    let cb = Callback {
+        sender: rt.data.sender.clone(),
+        screen_id: rt.current_output_id(),
+    };
+    let res = with_scene(rt, |lua| {
+        // cb is only seen by the chunk, the rest goes to the globals so
+        // they are kept between chunks
+        let env = lua.create_table()?;
+        env.set("cb", cb)?;
+        let meta = lua.create_table()?;
+        meta.set("__index", lua.globals())?;
+        meta.set("__newindex", lua.globals())?;
+        env.set_metatable(Some(meta));
+        let load = |code: &str| {
+            lua.load(code)
+                .set_name("=repl")
+                .set_environment(env.clone())
+                .into_function()
+        };
+        let func = match load(&format!("return {code}")) {
+            Ok(func) => func,
+            Err(_) => match load(code) {
+                Ok(func) => func,
+                Err(Error::SyntaxError {
+                    incomplete_input: true,
+                    ..
+                }) => return Ok(Eval::Incomplete),
+                Err(e) => return Err(e),
+            },
+        };
+        let tostring: Function = lua.globals().get("tostring")?;
+        let values = func
+            .call::<_, MultiValue>(())?
+            .into_iter()
+            .map(|value| tostring.call(value))
+            .collect::<mlua::Result<_>>()?;
+        Ok(Eval::Ok { values })
+    });
+    res.unwrap_or_else(|e| Eval::Error {
+        message: e.to_string(),
+    })
+}
+
+fn repl() -> anyhow::Result<ExitCode> {
+    let path = ipc::repl_socket_path().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let mut repl = ipc::Repl::connect(&path)
+        .map_err(|e| anyhow::anyhow!("Couldn't connect to {}: {e}", path.display()))?;
+    let stdin = std::io::stdin();
+    let prompt = |prompt: &str| {
+        // no prompts when the chunks are piped in
+        if stdin.is_terminal() {
+            print!("{prompt}");
+            let _ = std::io::stdout().flush();
+        }
+    };
+    let mut code = String::new();
+    prompt("> ");
+    for line in stdin.lock().lines() {
+        if !code.is_empty() {
+            code.push('\n');
+        }
+        code.push_str(&line?);
+        match repl.eval(&code).map_err(|e| anyhow::anyhow!("{e}"))? {
+            Eval::Ok { values } => {
+                if !values.is_empty() {
+                    println!("{}", values.join("\t"));
+                }
+            }
+            Eval::Incomplete => {
+                prompt(">> ");
+                continue;
+            }
+            Eval::Error { message } => eprintln!("{message}"),
+        }
+        code.clear();
+        prompt("> ");
+    }
+    if !code.is_empty() {
+        anyhow::bail!("Unfinished chunk at the end of the input");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn msg(request: Request) -> anyhow::Result<ExitCode> {
+    let path = ipc::socket_path().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let response = ipc::send(&path, &request)
+        .map_err(|e| anyhow::anyhow!("Couldn't talk to sketchover at {}: {e}", path.display()))?;
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    match response {
+        Response::Error { .. } => Ok(ExitCode::FAILURE),
+        _ => Ok(ExitCode::SUCCESS),
+    }
+}
+
+fn render(input: &Path, script: bool, out: &Path, size: (u32, u32)) -> anyhow::Result<()> {
+    let mut headless = if script {
+        let events = Headless::load_script(input).map_err(|e| anyhow::anyhow!("{e}"))?;
+        let mut headless = Headless::new();
+        headless
+            .replay(events)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        headless
+    } else {
+        Headless::from_save(input, size).map_err(|e| anyhow::anyhow!("{e}"))?
+    };
+    headless
+        .write_pngs(out)
+        .map_err(|e| anyhow::anyhow!("Couldn't write images: {e}"))
+}
+
+/// The annotations for the sketchover module, put them in a directory in
+/// the workspace.library of lua-language-server.
+fn lua_types() -> String {
+    let handler: &[&str] = &["func"];
+    LuaTypes::new()
+        .class::<RuntimeData>("Sketchover")
+        .params(&[
+            ("init", handler),
+            ("keypress", handler),
+            ("new_output", handler),
+            ("destroy_output", handler),
+            ("mousepress", handler),
+            ("scroll", handler),
+            ("ipc", handler),
+            ("shutdown", handler),
+            ("on", &["event", "func"]),
+            ("after", &["ms", "func"]),
+            ("every", &["ms", "func"]),
+            ("tool", &["name", "def"]),
+            ("signal", &["name", "func"]),
+            ("modifier_map", &["map"]),
+            ("snap", &["snap"]),
+        ])
+        .class::<Callback>("Callback")
+        .params(&[
+            ("shapes", &["screen_id"]),
+            ("remove_shape", &["index", "screen_id"]),
+            ("restyle", &["index", "style", "screen_id"]),
+            ("translate", &["index", "dx", "dy", "screen_id"]),
+            ("clear", &["screen_id"]),
+            ("undo", &["screen_id"]),
+            ("pause", &["screen_id"]),
+            ("unpause", &["screen_id"]),
+            ("set_fg", &["color", "screen_id"]),
+            ("save", &["screen_id"]),
+            ("boards", &["screen_id"]),
+            ("background", &["screen_id"]),
+            ("set_background", &["background", "screen_id"]),
+            ("new_board", &["name", "screen_id"]),
+            ("next_board", &["screen_id"]),
+            ("prev_board", &["screen_id"]),
+            ("delete_board", &["screen_id"]),
+            ("select_board", &["name", "screen_id"]),
+            ("set_style", &["style"]),
+            ("draw", &["tool", "pos", "style"]),
+        ])
+        .class::<LuaOutPut>("LuaOutPut")
+        .params(&[
+            ("save", &["path"]),
+            ("restore", &["path"]),
+            ("set_background", &["background"]),
+            ("new_board", &["name"]),
+            ("select_board", &["name"]),
+            ("set_fg", &["color"]),
+        ])
+        .class::<LuaKeyEvent>("LuaKeyEvent")
+        .class::<MouseEvent>("MouseEvent")
+        .class::<ScrollEvent>("ScrollEvent")
+        .class::<Handler>("Handler")
+        .class::<LuaTimer>("LuaTimer")
+        .class::<LuaPath>("LuaPath")
+        .params(&[
+            ("move_to", &["x", "y"]),
+            ("line_to", &["x", "y"]),
+            ("quad_to", &["cx", "cy", "x", "y"]),
+            ("cubic_to", &["cx1", "cy1", "cx2", "cy2", "x", "y"]),
+            ("arc", &["x", "y", "r", "start", "sweep"]),
+        ])
+        .render("sketchover", "Sketchover")
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    env_logger::init();
+    let args = Args::parse();
+    if args.emit_lua_types {
+        print!("{}", lua_types());
+        return Ok(ExitCode::SUCCESS);
+    }
+    match args.command {
+        Some(Commands::Msg { request }) => return msg(request),
+        Some(Commands::Repl) => return repl(),
+        Some(Commands::Render {
+            input,
+            script,
+            out,
+            width,
+            height,
+        }) => {
+            render(&input, script, &out, (width, height))?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    let script = args.config.or_else(|| {
+        let xdg_dirs = BaseDirectories::with_prefix("sketchover").ok()?;
+        xdg_dirs.find_config_file("init.lua")
+    });
+    make_lua_context(script.as_deref(), args.watch, args.repl)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use calloop::signals::Signal;
+    use calloop::EventLoop;
+    use mlua::{Function, Lua, Table, Value};
+    use raqote::{LineCap, LineJoin, SolidSource, StrokeStyle};
+    use sketchover::board::BoardOp;
+    use sketchover::runtime::{Events, Runtime};
+    use sketchover::tools::behaviour::ModifierMap;
+    use sketchover::tools::draw::draw::Draw;
+    use sketchover::tools::draw::line::Line;
+    use sketchover::tools::snap::Snap;
+    use sketchover::tools::Tool;
+
+    use sketchover::ipc::{self, Eval};
+
+    use super::{
+        draw_to_table, emit_sync_callback, emit_until, eval, handle_message, lua_tool,
+        make_lua_context, register_event, register_module, reload, run_script, shape_table, signal,
+        stop_value, table_to_draw, with_scene, Callback, LuaBindings, LuaOutPut, Message,
+        RegistryTools, RuntimeData, SAVE_FILE,
+    };
+
+    // A runtime that isn't connected to a compositor, with the module
+    // registered in its lua state
+    fn runtime(
+        script: Option<PathBuf>,
+    ) -> (
+        EventLoop<'static, Runtime<LuaBindings>>,
+        Runtime<LuaBindings>,
+    ) {
+        let event_loop = EventLoop::try_new().unwrap();
+        let (sender, receiver) = calloop::channel::channel();
+        event_loop
+            .handle()
+            .insert_source(receiver, |_, _, _| {})
+            .unwrap();
+        let sender = Rc::new(sender);
+        let lua = Rc::new(Lua::new());
+        let rt = Runtime::init(LuaBindings {
+            lua: lua.clone(),
+            sender: sender.clone(),
+            handle: event_loop.handle(),
+            script,
+        });
+        let module = RuntimeData {
+            sender,
+            running: None,
+        };
+        register_module(
+            &lua,
+            module,
+            event_loop.handle(),
+            RegistryTools::new(rt.tools()),
+        )
+        .unwrap();
+        (event_loop, rt)
+    }
+
+    #[test]
+    fn handlers() {
+        let lua = Lua::new();
+        let handler = |code: &str| {
+            let func: Function = lua.load(code).eval().unwrap();
+            register_event(&lua, ("keypress".to_owned(), func)).unwrap()
+        };
+        lua.load("calls = {}").exec().unwrap();
+        lua.globals().set("STOP", stop_value()).unwrap();
+        // only STOP stops the handlers after it
+        let first = handler("function(key) table.insert(calls, 'first ' .. key) return true end");
+        handler(
+            "function(key) table.insert(calls, 'second ' .. key) return key == 'q' and STOP end",
+        );
+        handler("function(key) table.insert(calls, 'third ' .. key) end");
+
+        let emit = |key: &str| emit_sync_callback(&lua, ("keypress".to_owned(), key.to_owned()));
+        assert!(emit("a").unwrap() == Value::Nil);
+        assert!(emit("q").unwrap() == Value::LightUserData(stop_value()));
+        lua.globals()
+            .set("first", lua.create_userdata(first).unwrap())
+            .unwrap();
+        assert!(lua.load("first:unregister()").eval::<bool>().unwrap());
+        assert!(!lua.load("first:unregister()").eval::<bool>().unwrap());
+        emit("b").unwrap();
+
+        let calls: Table = lua.globals().get("calls").unwrap();
+        let calls = calls
+            .sequence_values::<String>()
+            .collect::<mlua::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            calls,
+            ["first a", "second a", "third a", "first q", "second q", "second b", "third b"]
+        );
+    }
+
+    #[test]
+    fn script_errors() {
+        let path = std::env::temp_dir().join(format!("sketchover-{}.lua", std::process::id()));
+        std::fs::write(&path, "local function f()\n  error('boom')\nend\nf()\n").unwrap();
+        let err = make_lua_context(Some(&path), false, false)
+            .unwrap_err()
+            .to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            err.contains(&format!("{}:2: boom", path.display())),
+            "{err}"
+        );
+        assert!(err.contains("stack traceback"), "{err}");
+
+        assert!(make_lua_context(Some(&path), false, false).is_err());
+    }
+
+    #[test]
+    fn reload_script() {
+        let path =
+            std::env::temp_dir().join(format!("sketchover-reload-{}.lua", std::process::id()));
+        let (_event_loop, mut rt) = runtime(Some(path.clone()));
+        let version = |rt: &Runtime<LuaBindings>| {
+            let lua = &rt.data.lua;
+            match emit_until(lua, ("ipc".to_owned(), ()), |ret| ret.is_string()).unwrap() {
+                Value::String(s) => s.to_str().unwrap().to_owned(),
+                wat => panic!("unexpected {wat:?}"),
+            }
+        };
+        let script =
+            |version: &str| format!("require('sketchover').ipc(function() return '{version}' end)");
+
+        std::fs::write(&path, script("v1")).unwrap();
+        reload(&mut rt).unwrap();
+        assert_eq!(version(&rt), "v1");
+
+        // a broken script keeps the settings of the old one
+        let map = ModifierMap {
+            straighten: None,
+            ..Default::default()
+        };
+        rt.set_modifier_map(map.clone());
+        let snap = Snap {
+            grid: false,
+            distance: 2.,
+            ..Default::default()
+        };
+        rt.set_snap(snap);
+        std::fs::write(&path, "this isn't lua").unwrap();
+        assert!(reload(&mut rt).is_err());
+        assert_eq!(version(&rt), "v1");
+        assert_eq!(*rt.modifier_map(), map);
+        assert_eq!(*rt.snap(), snap);
+
+        // a tool from the old script stops updating, even when its lua
+        // state is still there like the first one is
+        rt.data
+            .lua
+            .load(
+                r#"
+                require("sketchover").tool("dot", {
+                    draw = function(state, path)
+                        path:arc(state.pos.x, state.pos.y, 1, 0, 1)
+                        path:fill()
+                    end,
+                })
+                "#,
+            )
+            .exec()
+            .unwrap();
+        let old = rt.data.lua.clone();
+        let mut tool = lua_tool(&old, "dot".to_owned(), (1., 1.), Draw::default()).unwrap();
+        tool.update((5., 5.));
+        let bounds = tool.bounds();
+        assert!(bounds.is_some());
+
+        std::fs::write(&path, script("v2")).unwrap();
+        reload(&mut rt).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(version(&rt), "v2");
+        tool.update((50., 50.));
+        assert_eq!(tool.bounds(), bounds);
+    }
+
+    #[test]
+    fn timers() {
+        let (mut event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            fired, ticks = 0, 0
+            sketchover.after(1, function() fired = fired + 1 end)
+            sketchover.after(1, function() fired = fired + 10 end):cancel()
+            local timer
+            timer = sketchover.every(1, function()
+                ticks = ticks + 1
+                if ticks == 3 then
+                    timer:cancel()
+                end
+            end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(lua
+            .load(r#"require("sketchover").every(0, function() end)"#)
+            .exec()
+            .is_err());
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(100) {
+            event_loop
+                .dispatch(Some(Duration::from_millis(5)), &mut rt)
+                .unwrap();
+        }
+        assert_eq!(lua.globals().get::<_, u32>("fired").unwrap(), 1);
+        assert_eq!(lua.globals().get::<_, u32>("ticks").unwrap(), 3);
+    }
+
+    #[test]
+    fn signals() {
+        let (_event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            usr1 = 0
+            sketchover.signal("sigusr1", function() usr1 = usr1 + 1 end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(lua
+            .load(r#"require("sketchover").signal("sigkill", function() end)"#)
+            .exec()
+            .is_err());
+
+        signal(&mut rt, Signal::SIGUSR1);
+        signal(&mut rt, Signal::SIGUSR2);
+        signal(&mut rt, Signal::SIGUSR1);
+        assert_eq!(lua.globals().get::<_, u32>("usr1").unwrap(), 2);
+
+        // commands return true, that doesn't stop sigterm from quitting
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            sketchover.signal("sigterm", function(cb) return cb:save() end)
+            sketchover.signal("sighup", function() return sketchover.STOP end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(signal(&mut rt, Signal::SIGTERM));
+        assert!(!signal(&mut rt, Signal::SIGHUP));
+    }
+
+    #[test]
+    fn tools() {
+        let (_event_loop, rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            sketchover.tool("cross", {
+                update = function(state)
+                    state.moves = (state.moves or 0) + 1
+                end,
+                draw = function(state, path)
+                    path:move_to(state.start.x, state.start.y)
+                    path:line_to(state.pos.x, state.pos.y)
+                    path:stroke()
+                    path:arc(state.pos.x, state.pos.y, state.moves, 0, 1)
+                    path:close()
+                    path:fill()
+                end,
+            })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let register = |name: &str| {
+            lua.load(format!(
+                r#"require("sketchover").tool("{name}", {{ draw = function() end }})"#
+            ))
+            .exec()
+        };
+        assert!(register("pen").is_err());
+        assert!(register("circle").is_err());
+
+        let mut tool = lua_tool(&lua, "cross".to_owned(), (1., 2.), Draw::default()).unwrap();
+        tool.update((3., 4.));
+        tool.update((5., 6.));
+        let saved = serde_json::to_value(&tool).unwrap();
+        assert_eq!(saved["tool"], "scripted");
+        assert_eq!(
+            saved["paints"],
+            serde_json::json!([
+                { "stroke": [{ "move_to": [1., 2.] }, { "line_to": [5., 6.] }] },
+                { "fill": [{ "arc": [5., 6., 2., 0., 1.] }, "close"] },
+            ])
+        );
+        assert!(lua_tool(&lua, "spray".to_owned(), (1., 2.), Draw::default()).is_err());
+    }
+
+    #[test]
+    fn default_script() {
+        // run returns right away, as the runtime isn't given to the module
+        let (_event_loop, rt) = runtime(None);
+        run_script(&rt.data.lua, None).unwrap();
+        assert!(lua_tool(&rt.data.lua, "arrow".to_owned(), (1., 2.), Draw::default()).is_ok());
+    }
+
+    #[test]
+    fn scene() {
+        let (_event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        let cb = Callback {
+            sender: rt.data.sender.clone(),
+            screen_id: None,
+        };
+        lua.globals().set("cb", cb).unwrap();
+        let shapes = || lua.load("return cb:shapes()").eval::<(Value, String)>();
+        let (found, err) = shapes().unwrap();
+        assert!(found == Value::Nil);
+        assert!(err.contains("only be used in a handler"), "{err}");
+
+        lua.load(
+            r#"
+            require("sketchover").signal("sigusr1", function(cb)
+                _, err = cb:remove_shape(1)
+                ok, style_err = pcall(cb.restyle, cb, 1, { style = "wide" })
+            end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        signal(&mut rt, Signal::SIGUSR1);
+        let err: String = lua.globals().get("err").unwrap();
+        assert_eq!(err, "There is no current output");
+        // bad arguments are raised, before the output is looked for
+        assert!(!lua.globals().get::<_, bool>("ok").unwrap());
+        // the runtime is gone again when the handler returns
+        assert!(shapes().unwrap().1.contains("only be used in a handler"));
+    }
+
+    #[test]
+    fn shutdown() {
+        let (_event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            calls = 0
+            sketchover.shutdown(function(cb) calls = calls + 1 end)
+            sketchover.on("shutdown", function(cb) calls = calls + 10 end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(lua
+            .load(r#"require("sketchover").on("explode", function() end)"#)
+            .exec()
+            .is_err());
+
+        LuaBindings::shutdown(&mut rt);
+        assert_eq!(lua.globals().get::<_, u32>("calls").unwrap(), 11);
+    }
+
+    #[test]
+    fn repl() {
+        let (mut event_loop, mut rt) = runtime(None);
+        let ok = |values: &[&str]| Eval::Ok {
+            values: values.iter().map(|v| v.to_string()).collect(),
+        };
+        assert_eq!(eval(&mut rt, "x = 1 + 1"), ok(&[]));
+        assert_eq!(eval(&mut rt, "x, type(cb)"), ok(&["2", "userdata"]));
+        // the cb of the script is left alone
+        rt.data.lua.globals().set("cb", "mine").unwrap();
+        assert_eq!(eval(&mut rt, "type(cb)"), ok(&["userdata"]));
+        assert_eq!(eval(&mut rt, "_G.cb"), ok(&["mine"]));
+        assert_eq!(eval(&mut rt, "for i = 1, 2 do"), Eval::Incomplete);
+        let Eval::Error { message } = eval(&mut rt, "error('boom')") else {
+            panic!("error wasn't returned");
+        };
+        assert!(message.contains("boom"), "{message}");
+
+        let path =
+            std::env::temp_dir().join(format!("sketchover-repl-{}.sock", std::process::id()));
+        ipc::listen_repl(&event_loop.handle(), path.clone(), eval).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let client = std::thread::spawn(move || {
+            let mut repl = ipc::Repl::connect(&path).unwrap();
+            (
+                repl.eval("x * 2").unwrap(),
+                repl.eval("function f()").unwrap(),
+            )
+        });
+        while !client.is_finished() {
+            event_loop
+                .dispatch(Some(Duration::from_millis(10)), &mut rt)
+                .unwrap();
+        }
+        let (double, incomplete) = client.join().unwrap();
+        assert_eq!(double, ok(&["4"]));
+        assert_eq!(incomplete, Eval::Incomplete);
+    }
+
+    #[test]
+    fn snap() {
+        let (_event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            local sketchover = require("sketchover")
+            ok = sketchover:snap({ enabled = true, distance = 4 })
+            bad = pcall(sketchover.snap, sketchover, { distance = "far" })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(lua.globals().get::<_, bool>("ok").unwrap());
+        assert!(!lua.globals().get::<_, bool>("bad").unwrap());
+
+        let snap = Snap {
+            enabled: true,
+            ..Default::default()
+        };
+        handle_message(&mut rt, Message::Snap(snap));
+        assert_eq!(*rt.snap(), snap);
+        handle_message(&mut rt, Message::ToggleSnap);
+        assert!(!rt.snap().enabled);
+    }
+
+    #[test]
+    fn commands() {
+        let (_event_loop, mut rt) = runtime(None);
+        let lua = rt.data.lua.clone();
+        lua.load(
+            r#"
+            require("sketchover").signal("sigusr1", function(cb)
+                -- more than a bounded channel would take
+                for _ = 1, 10 do
+                    assert(cb:stop_draw())
+                end
+                ok, err = cb:clear(42)
+                _, board_err = cb:select_board("notes", 42)
+                _, background_err = cb:set_background({ mode = "grid", spacing = 16 }, 42)
+                bad_background = pcall(cb.set_background, cb, "chalk")
+            end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        signal(&mut rt, Signal::SIGUSR1);
+        assert_eq!(lua.globals().get::<_, Option<bool>>("ok").unwrap(), None);
+        assert_eq!(
+            lua.globals().get::<_, String>("err").unwrap(),
+            "There is no output 42"
+        );
+        assert_eq!(
+            lua.globals().get::<_, String>("board_err").unwrap(),
+            "There is no output 42"
+        );
+        assert_eq!(
+            lua.globals().get::<_, String>("background_err").unwrap(),
+            "There is no output 42"
+        );
+        assert!(!lua.globals().get::<_, bool>("bad_background").unwrap());
+
+        // nil is the current output, not the one the event happened on
+        let cb = Callback {
+            sender: rt.data.sender.clone(),
+            screen_id: Some(42),
+        };
+        lua.globals().set("cb", cb).unwrap();
+        let (id, err) = with_scene(&mut rt, |lua| {
+            lua.load("return cb.screen_id, select(2, cb:clear())")
+                .eval::<(u32, String)>()
+        })
+        .unwrap();
+        assert_eq!(id, 42);
+        assert_eq!(err, "There is no current output");
+
+        // messages for outputs that are gone are logged
+        handle_message(&mut rt, Message::Clear(None));
+        handle_message(&mut rt, Message::Save(Some(42), PathBuf::from("nowhere")));
+        handle_message(&mut rt, Message::Board(None, BoardOp::Next));
+    }
+
+    #[test]
+    fn output_methods() {
+        let (sender, receiver) = calloop::channel::channel();
+        let lua = Lua::new();
+        let output = LuaOutPut {
+            id: 7,
+            created: None,
+            sender: Rc::new(sender),
+        };
+        lua.globals().set("output", output).unwrap();
+        lua.load(
+            r#"
+            assert(output.id == 7)
+            assert(output:save("drawing"))
+            assert(output:restore())
+            assert(output:pause())
+            assert(output:unpause())
+            assert(output:clear())
+            assert(output:undo())
+            assert(output:set_fg({ r = 255 }))
+            assert(output:enable())
+            assert(output:disable())
+            -- the info is gone with the output
+            assert(not pcall(function() return output.name end))
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        let messages: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        let id = Some(7);
+        assert!(matches!(
+            &messages[..],
+            [
+                Message::Save(save, saved),
+                Message::Restore(restore, restored),
+                Message::Pause(pause),
+                Message::Unpause(unpause),
+                Message::Clear(clear),
+                Message::Undo(undo),
+                Message::SetFg(color, fg),
+                Message::Passthrough(false, enable),
+                Message::Passthrough(true, disable),
+            ] if [save, restore, pause, unpause, clear, undo, fg, enable, disable]
+                .iter()
+                .all(|&&i| i == id)
+                && saved == &PathBuf::from("drawing")
+                && restored == &PathBuf::from(SAVE_FILE)
+                && color.r == 255
+        ));
+    }
+
+    #[test]
+    fn lua_types() {
+        let stubs = super::lua_types();
+        for line in [
+            "---@meta sketchover",
+            "---@field keypress fun(func: function): Handler",
+            "---@field quit fun(self: Callback): boolean?, string?",
+            "---@field clear fun(self: Callback, screen_id?: any): boolean?, string?",
+            "---@field new_board fun(self: Callback, name?: string, screen_id?: any): boolean?, string?",
+            "---@field save fun(self: LuaOutPut, path?: string): boolean?, string?",
+            "---@field pos table",
+            "---@type Sketchover",
+        ] {
+            assert!(
+                stubs.lines().any(|l| l == line),
+                "{line} missing in:\n{stubs}"
+            );
+        }
+    }
+
+    #[test]
+    fn shape_tables() {
+        let lua = Lua::new();
+        let mut line = Line::new((1., 2.), Draw::default());
+        line.update((4., 6.));
+        let table = shape_table(&lua, &line).unwrap();
+        assert_eq!(table.get::<_, String>("kind").unwrap(), "line");
+        let age: f64 = table.get("age").unwrap();
+        assert!((0. ..1.).contains(&age), "{age}");
+        let bounds: Table = table.get("bounds").unwrap();
+        assert_eq!(bounds.get::<_, f64>("width").unwrap(), 3.);
+    }
+
+    #[test]
+    fn draw_table_roundtrip() {
+        let lua = Lua::new();
+        let draw = Draw {
+            style: StrokeStyle {
+                width: 3.,
+                cap: LineCap::Square,
+                join: LineJoin::Bevel,
+                miter_limit: 2.,
+                dash_array: vec![1., 2.],
+                dash_offset: 0.5,
+            },
+            color: SolidSource {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 4,
+            },
+        };
+        let table = draw_to_table(&lua, &draw).unwrap();
+        assert_eq!(table_to_draw(table).unwrap(), draw);
+    }
+}
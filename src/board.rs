@@ -0,0 +1,193 @@
+// Boards are named pages of drawings, an output shows one of them at a
+// time. The drawings of the board that is shown are kept in the draws of
+// the output, so drawing and rendering don't know about boards, and are
+// swapped in and out when switching. Save files are laid out the same way.
+
+use serde::{Deserialize, Serialize};
+
+use crate::tools::registry;
+use crate::tools::Tool;
+
+#[derive(Serialize, Deserialize)]
+pub struct Board {
+    pub name: String,
+    /// Empty for the board that is shown, its drawings are in the output
+    #[serde(deserialize_with = "registry::deserialize_draws")]
+    pub draws: Vec<Box<dyn Tool>>,
+}
+
+impl Board {
+    fn new(name: String) -> Self {
+        Board {
+            name,
+            draws: Vec::new(),
+        }
+    }
+}
+
+/// What to do with the boards of an output
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoardOp {
+    /// Add a board after the current one and show it, unnamed boards are
+    /// numbered
+    New(Option<String>),
+    Next,
+    Prev,
+    /// Remove the board that is shown and show the one before it
+    Delete,
+    Select(String),
+}
+
+pub struct Boards {
+    boards: Vec<Board>,
+    current: usize,
+}
+
+impl Default for Boards {
+    fn default() -> Self {
+        Boards {
+            boards: vec![Board::new("1".to_owned())],
+            current: 0,
+        }
+    }
+}
+
+impl Boards {
+    /// Boards read from a save file, older saves don't have any. A board
+    /// index past the end shows the last board instead.
+    pub fn from_saved(boards: Vec<Board>, current: usize) -> Self {
+        if boards.is_empty() {
+            return Boards::default();
+        }
+        let current = current.min(boards.len() - 1);
+        Boards { boards, current }
+    }
+
+    pub fn list(&self) -> &[Board] {
+        &self.boards
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// The name of the board that is shown
+    pub fn name(&self) -> &str {
+        &self.boards[self.current].name
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.boards.iter().map(|board| board.name.as_str())
+    }
+
+    /// Apply op, shown is the drawings of the output
+    pub fn apply(&mut self, op: BoardOp, shown: &mut Vec<Box<dyn Tool>>) -> Result<(), String> {
+        let len = self.boards.len();
+        match op {
+            BoardOp::New(name) => {
+                let name = match name {
+                    Some(name) if self.position(&name).is_some() => {
+                        return Err(format!("There already is a board called {name}"))
+                    }
+                    Some(name) => name,
+                    None => (1..)
+                        .map(|n| n.to_string())
+                        .find(|name| self.position(name).is_none())
+                        .unwrap_or_default(),
+                };
+                self.boards.insert(self.current + 1, Board::new(name));
+                // the current board is still at the same index
+                self.show(self.current + 1, shown);
+            }
+            BoardOp::Next => self.show((self.current + 1) % len, shown),
+            BoardOp::Prev => self.show((self.current + len - 1) % len, shown),
+            BoardOp::Delete => {
+                if len == 1 {
+                    return Err("The last board can't be deleted".to_owned());
+                }
+                let deleted = self.current;
+                self.show(deleted.checked_sub(1).unwrap_or(1), shown);
+                self.boards.remove(deleted);
+                if self.current > deleted {
+                    self.current -= 1;
+                }
+            }
+            BoardOp::Select(name) => {
+                let idx = self
+                    .position(&name)
+                    .ok_or_else(|| format!("There is no board called {name}"))?;
+                self.show(idx, shown);
+            }
+        }
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.boards.iter().position(|board| board.name == name)
+    }
+
+    fn show(&mut self, idx: usize, shown: &mut Vec<Box<dyn Tool>>) {
+        std::mem::swap(shown, &mut self.boards[self.current].draws);
+        self.current = idx;
+        std::mem::swap(shown, &mut self.boards[self.current].draws);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoardOp, Boards};
+    use crate::tools::draw::draw::Draw;
+    use crate::tools::draw::pen::Pen;
+    use crate::tools::Tool;
+
+    #[test]
+    fn switch_boards() {
+        let mut boards = Boards::default();
+        let mut shown: Vec<Box<dyn Tool>> = vec![Box::new(Pen::new((0., 0.), Draw::default()))];
+
+        boards.apply(BoardOp::New(None), &mut shown).unwrap();
+        assert_eq!(boards.name(), "2");
+        assert!(shown.is_empty());
+        boards
+            .apply(BoardOp::New(Some("notes".to_owned())), &mut shown)
+            .unwrap();
+        assert!(boards
+            .apply(BoardOp::New(Some("notes".to_owned())), &mut shown)
+            .is_err());
+        assert_eq!(boards.names().collect::<Vec<_>>(), ["1", "2", "notes"]);
+
+        boards.apply(BoardOp::Next, &mut shown).unwrap();
+        assert_eq!((boards.name(), shown.len()), ("1", 1));
+        boards.apply(BoardOp::Prev, &mut shown).unwrap();
+        assert_eq!(boards.name(), "notes");
+        boards
+            .apply(BoardOp::Select("1".to_owned()), &mut shown)
+            .unwrap();
+        assert_eq!(shown.len(), 1);
+
+        // deleting the first board shows the one after it
+        boards.apply(BoardOp::Delete, &mut shown).unwrap();
+        assert_eq!((boards.name(), boards.current()), ("2", 0));
+        boards.apply(BoardOp::Next, &mut shown).unwrap();
+        boards.apply(BoardOp::Delete, &mut shown).unwrap();
+        assert_eq!(boards.names().collect::<Vec<_>>(), ["2"]);
+        assert!(boards.apply(BoardOp::Delete, &mut shown).is_err());
+        assert!(boards.list().iter().all(|board| board.draws.is_empty()));
+    }
+
+    #[test]
+    fn saved_boards() {
+        assert_eq!(Boards::from_saved(Vec::new(), 0).name(), "1");
+        let saved = || {
+            let mut boards = Boards::default();
+            boards
+                .apply(BoardOp::New(Some("notes".to_owned())), &mut Vec::new())
+                .unwrap();
+            boards.boards
+        };
+        let boards = Boards::from_saved(saved(), 5);
+        assert_eq!(boards.names().collect::<Vec<_>>(), ["1", "notes"]);
+        assert_eq!(boards.current(), 1);
+        assert_eq!(Boards::from_saved(saved(), 0).name(), "1");
+    }
+}
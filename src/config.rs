@@ -1,221 +1,350 @@
+// The declarative config used by the sketch binary, for when you don't
+// want to write any lua. It's read with confy from
+// $XDG_CONFIG_HOME/sketchover/default-config.ron and is created with the
+// default values if it doesn't exist.
+
 use std::collections::HashMap;
+use std::error;
+use std::path::PathBuf;
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use smithay_client_toolkit::seat::keyboard::Modifiers;
 
 use crate::{
+    background::{Background, Mode},
     keymap::KeyMap,
     mousemap::{Mouse, MouseEvent, MouseMap},
-    tools::DrawKind,
+    runtime::DEFAULT_SCROLL_THRESHOLD,
+    tools::{behaviour::ModifierMap, snap::Snap},
 };
 
-// #[derive(Parser)]
-// #[command(author, version, about, long_about = None)]
-// pub struct Args {
-//     /// The size of the pen used for drawing
-//     #[clap(short, long)]
-//     size: Option<f32>,
-//
-//     /// Colors in the palette, first value will be the starting color
-//     #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ' ')]
-//     palette: Option<Vec<String>>,
-//
-//     #[clap(short, long)]
-//     distance: Option<bool>,
-//
-//     // #[clap(long, value_enum)]
-//     // unit: Option<Unit>,
-//     /// Foreground color
-//     #[clap(short, long)]
-//     background: Option<String>,
-//
-//     #[clap(long)]
-//     text_color: Option<String>,
-//
-//     #[clap(short = 't', long, value_enum)]
-//     starting_tool: Option<DrawKind>,
-//
-//     #[clap(long)]
-//     font: Option<String>,
-//
-//     #[clap(long)]
-//     font_size: Option<f32>,
-//
-//     /// Should sketchover start in paused state
-//     #[clap(long)]
-//     paused: Option<bool>,
-//
-//     /// How senisitve should scrolling be
-//     #[clap(long)]
-//     pub scroll_treshold: Option<f64>,
-//
-//     /// Save on exit
-//     #[clap(long)]
-//     pub save_on_exit: Option<bool>,
-//
-//     /// Delete the save file after resuming
-//     #[clap(long)]
-//     pub delete_save_on_resume: Option<bool>,
-// }
-
-// #[derive(Serialize, Deserialize)]
-// pub struct Config {
-//     pub size: f32,
-//     pub palette: Vec<String>,
-//     pub distance: bool,
-//     pub scroll_margine: f64,
-//     pub background: String,
-//     pub alt_background: String,
-//     pub text_color: String,
-//     pub tools: Vec<DrawKind>,
-//     pub font: Option<String>,
-//     pub font_size: f32,
-//     pub paused: bool,
-//     pub scroll_threshold: f64,
-//     pub save_on_exit: bool,
-//     pub delete_save_on_resume: bool,
-//     // pub scroll_treshold: f64,
-//     pub key_map: HashMap<KeyMap, Command>,
-//     pub mouse_map: HashMap<MouseMap, Command>,
-// }
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Parser, Debug, Default)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Read the config from this file instead of the default location
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    /// The size of the pen used for drawing
+    #[clap(short, long)]
+    pub size: Option<f32>,
+
+    /// The starting color, put first in the palette
+    #[clap(short, long)]
+    pub color: Option<String>,
+
+    /// Colors in the palette, first value will be the starting color
+    #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ' ')]
+    pub palette: Option<Vec<String>>,
+
+    /// Foreground color
+    #[clap(short, long)]
+    pub foreground: Option<String>,
+
+    /// The tool to start with, needs to be one of the tools in the config
+    #[clap(short = 't', long)]
+    pub starting_tool: Option<String>,
+
+    /// How many pixels of touchpad scrolling makes one scroll step
+    #[clap(long)]
+    pub scroll_threshold: Option<f64>,
+
+    /// Save on exit
+    #[clap(long)]
+    pub save_on_exit: Option<bool>,
+
+    /// Delete the save file after resuming from it, a save that can't
+    /// be read is kept
+    #[clap(long)]
+    pub delete_save_on_resume: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub size: f32,
+    pub palette: Vec<String>,
+    pub foreground: String,
+    /// Foreground used when toggling with ToggleFg
+    pub alt_foreground: String,
+    /// The background of outputs that weren't saved
+    pub background: Background,
+    /// Tools to cycle through, the first one is the starting tool
+    pub tools: Vec<String>,
+    pub scroll_threshold: f64,
+    pub save_on_exit: bool,
+    pub delete_save_on_resume: bool,
+    pub key_map: HashMap<KeyMap, Command>,
+    pub mouse_map: HashMap<MouseMap, Command>,
+    /// Modifiers that change how the tool behaves while drawing
+    pub modifier_map: ModifierMap,
+    /// What the shapes snap to, enabled is if it's on at the start
+    pub snap: Snap,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Command {
     Clear,
     Undo,
-    // Should these just be a style?
+    /// Index into the palette
     SetColor(usize),
+    NextColor,
+    PrevColor,
+    /// Index into the tools
     SetTool(usize),
-    SetDistance,
-    DisableDistance,
+    NextTool,
+    PrevTool,
     IncreaseSize(f32),
     DecreaseSize(f32),
+    TogglePause,
     SetPause,
     SetUnpause,
+    TogglePassthrough,
     Save,
-    SetFg,
+    ToggleFg,
+    /// Add a board after the one shown and show it
+    NewBoard,
+    NextBoard,
+    PrevBoard,
+    /// Delete the board that is shown
+    DeleteBoard,
+    SetBackground(Mode),
+    /// Cycle through the background modes
+    NextBackground,
+    /// Turn snapping to the grid and to other shapes on or off
+    ToggleSnap,
+    /// Start drawing with the current tool, until the button is released
+    DrawStart,
+    /// Run all the commands in order
+    Combo(Vec<Command>),
     Nop,
     Quit,
-    DrawStart,
 }
 
-// impl Command {
-//     pub fn draw_command(&self) -> bool {
-//         matches!(self, Command::DrawStart(_, _))
-//     }
-// }
-
-// impl Default for Config {
-//     fn default() -> Self {
-//         let mut key_map = HashMap::new();
-//         key_map.insert(KeyMap::new("c", Modifiers::default()), Command::Clear);
-//         key_map.insert(KeyMap::new("u", Modifiers::default()), Command::Undo);
-//         key_map.insert(KeyMap::new("n", Modifiers::default()), Command::NextColor);
-//         key_map.insert(KeyMap::new("N", Modifiers::default()), Command::PrevColor);
-//         key_map.insert(KeyMap::new("t", Modifiers::default()), Command::NextTool);
-//         key_map.insert(KeyMap::new("T", Modifiers::default()), Command::PrevTool);
-//         key_map.insert(
-//             KeyMap::new("d", Modifiers::default()),
-//             Command::ToggleDistance,
-//         );
-//         key_map.insert(
-//             KeyMap::new("plus", Modifiers::default()),
-//             Command::IncreaseSize(1.),
-//         );
-//         key_map.insert(
-//             KeyMap::new("minus", Modifiers::default()),
-//             Command::DecreaseSize(1.),
-//         );
-//         key_map.insert(KeyMap::new("p", Modifiers::default()), Command::TogglePause);
-//         key_map.insert(
-//             KeyMap::new("m", Modifiers::default()),
-//             Command::Combo(vec![Command::Clear, Command::IncreaseSize(1.)]),
-//         );
-//         key_map.insert(
-//             KeyMap::new("q", Modifiers::default()),
-//             Command::DrawStart(0, 0),
-//         );
-//         key_map.insert(KeyMap::new("b", Modifiers::default()), Command::ToggleFg);
-//         key_map.insert(KeyMap::new("s", Modifiers::default()), Command::Save);
-//
-//         let mut mouse_map = HashMap::new();
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::Button(MouseEvent::BtnLeft), Modifiers::default()),
-//             Command::DrawStart(0, 0),
-//         );
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::Button(MouseEvent::BtnRight), Modifiers::default()),
-//             Command::DrawStart(0, 1),
-//         );
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::ScrollUp, Modifiers::default()),
-//             Command::IncreaseSize(0.2),
-//         );
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::ScrollDown, Modifiers::default()),
-//             Command::DecreaseSize(0.2),
-//         );
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::Button(MouseEvent::BtnSide), Modifiers::default()),
-//             Command::PrevTool,
-//         );
-//         mouse_map.insert(
-//             MouseMap::new(Mouse::Button(MouseEvent::BtnExtra), Modifiers::default()),
-//             Command::NextTool,
-//         );
-//
-//         Config {
-//             size: 1.,
-//             palette: vec![
-//                 "#FF0000FF".to_owned(),
-//                 "#00FF00FF".to_owned(),
-//                 "#0000FFFF".to_owned(),
-//             ],
-//             distance: false,
-//             background: String::from("#FFFFFF40"),
-//             alt_background: String::from("#80808000"),
-//             text_color: String::from("#FFFFFFFF"),
-//             tools: vec![DrawKind::Pen, DrawKind::Line, DrawKind::Rect],
-//             font: None,
-//             scroll_margine: 3.0,
-//             font_size: 12.,
-//             paused: false,
-//             key_map,
-//             mouse_map,
-//             scroll_threshold: 5.,
-//             save_on_exit: false,
-//             delete_save_on_resume: true,
-//         }
-//     }
-// }
-
-// macro_rules! overwrite {
-//     ($var:expr, $replace:expr) => {
-//         if let Some(replace) = $replace {
-//             $var = replace;
-//         }
-//     };
-// }
-//
-// impl Config {
-//     pub fn load(args: Args) -> Result<Config, confy::ConfyError> {
-//         let mut cfg: Config = confy::load("sketchover", None)?;
-//         // TODO: This is beyond horrible but I'm not writing this lib right now
-//
-//         overwrite!(cfg.size, args.size);
-//         overwrite!(cfg.palette, args.palette);
-//         overwrite!(cfg.distance, args.distance);
-//         overwrite!(cfg.background, args.background);
-//         overwrite!(cfg.text_color, args.text_color);
-//         overwrite!(cfg.save_on_exit, args.save_on_exit);
-//         overwrite!(cfg.delete_save_on_resume, args.delete_save_on_resume);
-//         // overwrite!(cfg.starting_tool, args.starting_tool);
-//         if let Some(font) = args.font {
-//             cfg.font = Some(font);
-//         }
-//         overwrite!(cfg.font_size, args.font_size);
-//         overwrite!(cfg.paused, args.paused);
-//         Ok(cfg)
-//     }
-// }
+impl Default for Config {
+    fn default() -> Self {
+        let key = |name| KeyMap::new(name, Modifiers::default());
+        let mut key_map = HashMap::new();
+        key_map.insert(key("c"), Command::Clear);
+        key_map.insert(key("u"), Command::Undo);
+        key_map.insert(key("n"), Command::NextColor);
+        key_map.insert(key("N"), Command::PrevColor);
+        key_map.insert(key("t"), Command::NextTool);
+        key_map.insert(key("T"), Command::PrevTool);
+        key_map.insert(key("plus"), Command::IncreaseSize(1.));
+        key_map.insert(key("minus"), Command::DecreaseSize(1.));
+        key_map.insert(key("p"), Command::TogglePause);
+        key_map.insert(key("b"), Command::ToggleFg);
+        key_map.insert(key("s"), Command::Save);
+        key_map.insert(key("Q"), Command::Quit);
+        key_map.insert(key("Page_Down"), Command::NextBoard);
+        key_map.insert(key("Page_Up"), Command::PrevBoard);
+        key_map.insert(key("g"), Command::NextBackground);
+        key_map.insert(key("a"), Command::ToggleSnap);
+
+        let button = |event| MouseMap::new(Mouse::Button(event), Modifiers::default());
+        let mut mouse_map = HashMap::new();
+        mouse_map.insert(button(MouseEvent::BtnLeft), Command::DrawStart);
+        mouse_map.insert(button(MouseEvent::BtnSide), Command::PrevTool);
+        mouse_map.insert(button(MouseEvent::BtnExtra), Command::NextTool);
+        mouse_map.insert(
+            MouseMap::new(Mouse::ScrollUp, Modifiers::default()),
+            Command::IncreaseSize(0.2),
+        );
+        mouse_map.insert(
+            MouseMap::new(Mouse::ScrollDown, Modifiers::default()),
+            Command::DecreaseSize(0.2),
+        );
+
+        Config {
+            size: 1.,
+            palette: vec![
+                "#FF0000FF".to_owned(),
+                "#00FF00FF".to_owned(),
+                "#0000FFFF".to_owned(),
+            ],
+            foreground: String::from("#00000000"),
+            alt_foreground: String::from("#FFFFFF40"),
+            background: Background::default(),
+            tools: vec!["pen".to_owned(), "line".to_owned(), "rect".to_owned()],
+            scroll_threshold: DEFAULT_SCROLL_THRESHOLD,
+            save_on_exit: false,
+            delete_save_on_resume: true,
+            key_map,
+            mouse_map,
+            modifier_map: ModifierMap::default(),
+            snap: Snap::default(),
+        }
+    }
+}
+
+macro_rules! overwrite {
+    ($var:expr, $replace:expr) => {
+        if let Some(replace) = $replace {
+            $var = replace;
+        }
+    };
+}
+
+impl Config {
+    /// Load the config file, or the file in args, and apply the
+    /// arguments on top of it.
+    pub fn load(args: Args) -> Result<Config, Box<dyn error::Error>> {
+        let mut cfg: Config = match args.config {
+            Some(ref path) => confy::load_path(path)?,
+            None => confy::load("sketchover", None)?,
+        };
+        cfg.overwrite(args)?;
+        Ok(cfg)
+    }
+
+    pub fn overwrite(&mut self, args: Args) -> Result<(), Box<dyn error::Error>> {
+        overwrite!(self.size, args.size);
+        overwrite!(self.palette, args.palette);
+        overwrite!(self.foreground, args.foreground);
+        overwrite!(self.scroll_threshold, args.scroll_threshold);
+        overwrite!(self.save_on_exit, args.save_on_exit);
+        overwrite!(self.delete_save_on_resume, args.delete_save_on_resume);
+        if let Some(color) = args.color {
+            self.palette.insert(0, color);
+        }
+        if let Some(tool) = args.starting_tool {
+            let idx = self
+                .tools
+                .iter()
+                .position(|t| *t == tool)
+                .ok_or_else(|| format!("{tool} is not one of the configured tools"))?;
+            // keep the order we cycle the tools in
+            self.tools.rotate_left(idx);
+        }
+        Ok(())
+    }
+
+    /// Find the command bound to a key. Shift is already applied to the
+    /// keysym, so "N" matches shift+n without listing shift.
+    pub fn key_command(&self, key: KeyMap) -> Option<&Command> {
+        if let Some(command) = self.key_map.get(&key) {
+            return Some(command);
+        }
+        if key.modifier.shift {
+            let mut modifier = key.modifier;
+            modifier.shift = false;
+            return self.key_map.get(&KeyMap {
+                key: key.key,
+                modifier,
+            });
+        }
+        None
+    }
+
+    pub fn mouse_command(&self, mouse: MouseMap) -> Option<&Command> {
+        self.mouse_map.get(&mouse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use smithay_client_toolkit::seat::keyboard::Modifiers;
+
+    use super::{Args, Command, Config};
+    use crate::keymap::KeyMap;
+    use crate::mousemap::{Mouse, MouseEvent, MouseMap};
+    use crate::tools::behaviour::Modifier;
+
+    fn load_str(name: &str, config: &str) -> Config {
+        let path =
+            std::env::temp_dir().join(format!("sketchover-{name}-{}.ron", std::process::id()));
+        std::fs::write(&path, config).unwrap();
+        let cfg = confy::load_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        cfg.unwrap()
+    }
+
+    #[test]
+    fn default_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("sketchover-default-{}.ron", std::process::id()));
+        confy::store_path(&path, Config::default()).unwrap();
+        let cfg: Result<Config, _> = confy::load_path(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let cfg = cfg.unwrap();
+        let default = Config::default();
+        assert_eq!(cfg.palette, default.palette);
+        assert_eq!(cfg.key_map, default.key_map);
+        assert_eq!(cfg.mouse_map, default.mouse_map);
+        assert_eq!(cfg.modifier_map, default.modifier_map);
+    }
+
+    #[test]
+    fn partial_config() {
+        let cfg = load_str(
+            "partial",
+            r#"(size: 3.0, key_map: { (key: "x", modifier: ["ctrl"]): Combo([Clear, Quit]) },
+                modifier_map: (snap: None, from_centre: Some(logo)))"#,
+        );
+        assert_eq!(cfg.size, 3.0);
+        assert_eq!(cfg.modifier_map.snap, None);
+        assert_eq!(cfg.modifier_map.from_centre, Some(Modifier::Logo));
+        assert_eq!(cfg.modifier_map.square, Some(Modifier::Shift));
+        assert_eq!(cfg.tools, Config::default().tools);
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            cfg.key_command(KeyMap::new("x", ctrl)),
+            Some(&Command::Combo(vec![Command::Clear, Command::Quit]))
+        );
+        assert_eq!(
+            cfg.key_command(KeyMap::new("c", Modifiers::default())),
+            None
+        );
+    }
+
+    #[test]
+    fn shifted_keys() {
+        let cfg = Config::default();
+        let shift = Modifiers {
+            shift: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            cfg.key_command(KeyMap::new("N", shift)),
+            Some(&Command::PrevColor)
+        );
+        assert_eq!(
+            cfg.mouse_command(MouseMap::new(
+                Mouse::Button(MouseEvent::BtnLeft),
+                Modifiers::default()
+            )),
+            Some(&Command::DrawStart)
+        );
+    }
+
+    #[test]
+    fn args_overwrite() {
+        let mut cfg = Config::default();
+        let args = Args::try_parse_from([
+            "sketch",
+            "--size",
+            "4",
+            "-c",
+            "#FFFFFFFF",
+            "-p",
+            "#000000FF #111111FF",
+            "-t",
+            "line",
+        ])
+        .unwrap();
+        cfg.overwrite(args).unwrap();
+        assert_eq!(cfg.size, 4.);
+        assert_eq!(cfg.palette, ["#FFFFFFFF", "#000000FF", "#111111FF"]);
+        assert_eq!(cfg.tools, ["line", "rect", "pen"]);
+
+        let args = Args::try_parse_from(["sketch", "-t", "spray"]).unwrap();
+        assert!(cfg.overwrite(args).is_err());
+    }
+}
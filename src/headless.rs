@@ -0,0 +1,347 @@
+// A runtime without a compositor. Outputs are buffers in memory that
+// can be filled from a save file or by replaying a sequence of events,
+// useful for tests and for exporting drawings as images.
+
+use std::error;
+use std::fs::File;
+use std::path::{Component, Path};
+
+use raqote::{DrawTarget, SolidSource};
+use serde::{Deserialize, Serialize};
+
+use crate::background::Background;
+use crate::output;
+use crate::render::Scene;
+use crate::tools::{draw::draw::Draw, registry::ToolRegistry, Tool};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ScriptEvent {
+    /// Add an output, the first output added gets the pointer
+    Output {
+        id: u32,
+        #[serde(default)]
+        name: Option<String>,
+        width: u32,
+        height: u32,
+    },
+    /// Move the pointer to another output
+    Enter {
+        id: u32,
+    },
+    /// Start drawing with a tool
+    Start {
+        tool: String,
+        pos: (f64, f64),
+        #[serde(default)]
+        draw: Draw,
+    },
+    Motion {
+        pos: (f64, f64),
+    },
+    Stop,
+    Undo,
+    Clear,
+    SetFg {
+        color: String,
+    },
+    SetBackground {
+        background: Background,
+    },
+}
+
+pub struct HeadlessOutput {
+    pub id: u32,
+    pub name: Option<String>,
+    pub make: String,
+    pub model: String,
+    pub width: u32,
+    pub height: u32,
+    pub fgcolor: SolidSource,
+    pub background: Background,
+    pub draws: Vec<Box<dyn Tool>>,
+    buffer: Vec<u32>,
+}
+
+impl HeadlessOutput {
+    pub fn new(id: u32, width: u32, height: u32) -> Self {
+        HeadlessOutput {
+            id,
+            name: None,
+            make: String::new(),
+            model: String::new(),
+            width,
+            height,
+            fgcolor: SolidSource {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+            background: Background::default(),
+            draws: Vec::new(),
+            buffer: vec![0; width as usize * height as usize],
+        }
+    }
+
+    /// Render the output and return the pixels, premultiplied argb
+    pub fn render(&mut self) -> &[u32] {
+        let mut dt =
+            DrawTarget::from_backing(self.width as i32, self.height as i32, &mut self.buffer[..]);
+        let mut scene = Scene::new(self.fgcolor, &self.draws);
+        scene.background = self.background;
+        scene.render(&mut dt);
+        &self.buffer
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn error::Error>> {
+        self.render();
+        let dt =
+            DrawTarget::from_backing(self.width as i32, self.height as i32, &mut self.buffer[..]);
+        dt.write_png(path)?;
+        Ok(())
+    }
+
+    /// A name usable as a file name. The name comes from the save file,
+    /// so one that isn't a plain file name, like ../x, uses the id.
+    pub fn file_name(&self) -> String {
+        match self.name {
+            Some(ref name) if is_file_name(name) => name.clone(),
+            _ => self.id.to_string(),
+        }
+    }
+}
+
+fn is_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(first)), None) => first == name,
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+pub struct Headless {
+    outputs: Vec<HeadlessOutput>,
+    current_output: Option<usize>,
+    drawing: bool,
+    tools: ToolRegistry,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Headless::default()
+    }
+
+    /// Create outputs from a save file. Older save files don't have
+    /// the size of the output, for those size is used.
+    pub fn from_save<P: AsRef<Path>>(
+        path: P,
+        size: (u32, u32),
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let mut headless = Headless::new();
+        for saved in output::load(path)? {
+            let mut output = HeadlessOutput::new(
+                saved.id,
+                saved.width.unwrap_or(size.0),
+                saved.height.unwrap_or(size.1),
+            );
+            output.name = saved.name;
+            output.make = saved.make;
+            output.model = saved.model;
+            output.draws = saved.draws;
+            output.background = saved.background.unwrap_or_default();
+            headless.add_output(output);
+        }
+        Ok(headless)
+    }
+
+    pub fn add_output(&mut self, output: HeadlessOutput) {
+        self.outputs.push(output);
+        if self.current_output.is_none() {
+            self.current_output = Some(self.outputs.len() - 1);
+        }
+    }
+
+    pub fn outputs(&self) -> &[HeadlessOutput] {
+        &self.outputs
+    }
+
+    pub fn outputs_mut(&mut self) -> &mut [HeadlessOutput] {
+        &mut self.outputs
+    }
+
+    fn current(&mut self) -> Result<&mut HeadlessOutput, Box<dyn error::Error>> {
+        match self.current_output {
+            Some(idx) => Ok(&mut self.outputs[idx]),
+            None => Err("No output to draw on".into()),
+        }
+    }
+
+    pub fn apply(&mut self, event: ScriptEvent) -> Result<(), Box<dyn error::Error>> {
+        match event {
+            ScriptEvent::Output {
+                id,
+                name,
+                width,
+                height,
+            } => {
+                let mut output = HeadlessOutput::new(id, width, height);
+                output.name = name;
+                self.add_output(output);
+            }
+            ScriptEvent::Enter { id } => {
+                let idx = self
+                    .outputs
+                    .iter()
+                    .position(|o| o.id == id)
+                    .ok_or_else(|| format!("No output with id {id}"))?;
+                self.current_output = Some(idx);
+            }
+            ScriptEvent::Start { tool, pos, draw } => {
+                let tool = self.tools.create(&tool, pos, draw)?;
+                self.current()?.draws.push(tool);
+                self.drawing = true;
+            }
+            ScriptEvent::Motion { pos } => {
+                if self.drawing {
+                    if let Some(last) = self.current()?.draws.last_mut() {
+                        last.update(pos);
+                    }
+                }
+            }
+            ScriptEvent::Stop => self.drawing = false,
+            ScriptEvent::Undo => {
+                self.current()?.draws.pop();
+            }
+            ScriptEvent::Clear => self.current()?.draws = Vec::new(),
+            ScriptEvent::SetFg { color } => {
+                let hex = hex_color::HexColor::parse(&color)?;
+                self.current()?.fgcolor = SolidSource {
+                    r: hex.r,
+                    g: hex.g,
+                    b: hex.b,
+                    a: hex.a,
+                };
+            }
+            ScriptEvent::SetBackground { background } => self.current()?.background = background,
+        }
+        Ok(())
+    }
+
+    pub fn replay<I>(&mut self, events: I) -> Result<(), Box<dyn error::Error>>
+    where
+        I: IntoIterator<Item = ScriptEvent>,
+    {
+        for event in events {
+            self.apply(event)?;
+        }
+        Ok(())
+    }
+
+    /// Read a script, a json list of events
+    pub fn load_script<P: AsRef<Path>>(path: P) -> Result<Vec<ScriptEvent>, Box<dyn error::Error>> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Write every output as a png in dir
+    pub fn write_pngs<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), Box<dyn error::Error>> {
+        for output in self.outputs.iter_mut() {
+            let path = dir.as_ref().join(format!("{}.png", output.file_name()));
+            output.write_png(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Headless, HeadlessOutput, ScriptEvent};
+
+    #[test]
+    fn replay_script() {
+        let script = r##"[
+            {"event": "output", "id": 1, "width": 20, "height": 20},
+            {"event": "set_fg", "color": "#000000ff"},
+            {"event": "start", "tool": "line", "pos": [2.0, 10.0],
+             "draw": {"color": "#ffffffff", "style": {"width": 2.0, "cap": "butt",
+                "join": "miter", "miter_limit": 10.0, "dash_array": [], "dash_offset": 0.0}}},
+            {"event": "motion", "pos": [18.0, 10.0]},
+            {"event": "stop"}
+        ]"##;
+        let events: Vec<ScriptEvent> = serde_json::from_str(script).unwrap();
+        let mut headless = Headless::new();
+        headless.replay(events).unwrap();
+
+        let output = &mut headless.outputs_mut()[0];
+        assert_eq!(output.draws.len(), 1);
+        let pixels = output.render();
+        assert_eq!(pixels[10 * 20 + 10], 0xffffffff);
+        assert_eq!(pixels[0], 0xff000000);
+
+        let event = r#"{"event": "set_background", "background": {"mode": "whiteboard"}}"#;
+        headless
+            .apply(serde_json::from_str(event).unwrap())
+            .unwrap();
+        assert_eq!(headless.outputs_mut()[0].render()[0], 0xffffffff);
+    }
+
+    #[test]
+    fn from_save() {
+        let saved = r##"[{"id": 3, "model": "m", "make": "k", "name": "DP-1",
+            "width": 10, "height": 10, "draws": [{"tool": "rect",
+            "draw": {"color": "#ffffffff", "style": {"width": 1.0, "cap": "butt",
+                "join": "miter", "miter_limit": 10.0, "dash_array": [], "dash_offset": 0.0}},
+            "start": [2.0, 2.0], "stop": [4.0, 4.0], "_square": false, "_fill": false}]}]"##;
+        let path = std::env::temp_dir().join(format!("sketchover-save-{}", std::process::id()));
+        std::fs::write(&path, saved).unwrap();
+        let headless = Headless::from_save(&path, (100, 100));
+        std::fs::remove_file(&path).unwrap();
+
+        let headless = headless.unwrap();
+        let output = &headless.outputs()[0];
+        assert_eq!(output.file_name(), "DP-1");
+        assert_eq!((output.width, output.height), (10, 10));
+        assert_eq!(output.draws.len(), 1);
+        let j = serde_json::to_value(&output.draws).unwrap();
+        assert_eq!(j[0]["tool"], "rect");
+    }
+
+    #[test]
+    fn file_names() {
+        let mut output = HeadlessOutput::new(3, 1, 1);
+        assert_eq!(output.file_name(), "3");
+        for (name, file_name) in [
+            ("DP-1", "DP-1"),
+            ("../../x", "3"),
+            ("a/b", "3"),
+            ("/x", "3"),
+            ("..", "3"),
+            ("x/", "3"),
+            ("", "3"),
+        ] {
+            output.name = Some(name.to_owned());
+            assert_eq!(output.file_name(), file_name);
+        }
+    }
+
+    #[test]
+    fn unknown_tool() {
+        let mut headless = Headless::new();
+        headless
+            .apply(ScriptEvent::Output {
+                id: 1,
+                name: None,
+                width: 10,
+                height: 10,
+            })
+            .unwrap();
+        let res = headless.apply(ScriptEvent::Start {
+            tool: "spray".to_owned(),
+            pos: (0.0, 0.0),
+            draw: Default::default(),
+        });
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,691 @@
+// A control socket so sketchover can be driven from the outside, the
+// same way swaymsg drives sway. Every connection sends one json
+// encoded request terminated by a newline and gets one json response
+// back before the socket is closed. The repl socket keeps the
+// connection open and answers every lua chunk sent to it instead.
+
+use std::env;
+use std::error;
+use std::fs::{self, Permissions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use calloop::generic::Generic;
+use calloop::{Interest, LoopHandle, Mode, PostAction};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use xdg::BaseDirectories;
+
+use crate::background::{self, Background, Change};
+use crate::board::BoardOp;
+use crate::runtime::{Events, Runtime};
+use crate::tools::draw::draw::parse_solid;
+use crate::tools::snap::Snap;
+
+/// The env variable used to override the socket path
+pub const SOCKET_ENV: &str = "SKETCHOVER_SOCK";
+
+/// The env variable used to override the repl socket path
+pub const REPL_SOCKET_ENV: &str = "SKETCHOVER_REPL_SOCK";
+
+/// The longest line read from a socket, the connection is dropped when
+/// a line doesn't end before this
+const MAX_LINE: usize = 1 << 20;
+
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Remove all drawings from an output
+    Clear {
+        /// Output id, defaults to the output with the pointer
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Remove the latest drawing from an output
+    Undo {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Freeze the screen behind the drawings
+    Pause {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Unfreeze the screen behind the drawings
+    Unpause {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Set the drawing colour, as a hex string. Example "#FF0000FF"
+    SetColor { color: String },
+    /// Set the drawing tool. Example pen, line, rect
+    SetTool { tool: String },
+    /// Save the drawings of an output
+    Save {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+        /// Where to save, defaults to "sketchover"
+        #[arg(short, long)]
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Let input pass through to the windows below, toggles if no
+    /// value is given
+    Passthrough {
+        #[arg(action = clap::ArgAction::Set)]
+        #[serde(default)]
+        enable: Option<bool>,
+    },
+    /// Snap drawing to the grid and to other shapes, toggles if no value
+    /// is given
+    Snap {
+        #[arg(action = clap::ArgAction::Set)]
+        #[serde(default)]
+        enable: Option<bool>,
+    },
+    /// Add a board after the one that is shown and show it. Boards
+    /// without a name are numbered.
+    NewBoard {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// Show the next board
+    NextBoard {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Show the previous board
+    PrevBoard {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Delete the board that is shown, the last board can't be deleted
+    DeleteBoard {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+    },
+    /// Show the board with the name
+    SelectBoard {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+        name: String,
+    },
+    /// Set what is drawn behind the drawings, the spacing and colour are
+    /// used by the patterns
+    SetBackground {
+        #[arg(short, long)]
+        #[serde(default)]
+        output: Option<u32>,
+        #[serde(default)]
+        mode: Option<background::Mode>,
+        /// Pixels between the lines or dots
+        #[arg(short, long)]
+        #[serde(default)]
+        spacing: Option<f32>,
+        /// As a hex string. Example "#80808080"
+        #[arg(short, long)]
+        #[serde(default)]
+        color: Option<String>,
+    },
+    /// Run the configuration script again, the drawings are kept
+    Reload,
+    /// Quit sketchover
+    Quit,
+    /// List all outputs
+    GetOutputs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutputStatus {
+    pub id: u32,
+    pub name: Option<String>,
+    pub make: String,
+    pub model: String,
+    pub width: u32,
+    pub height: u32,
+    pub paused: bool,
+    /// The drawings on the board that is shown
+    pub draws: usize,
+    pub board: String,
+    pub boards: Vec<String>,
+    pub background: Background,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Outputs { outputs: Vec<OutputStatus> },
+    Error { message: String },
+}
+
+impl Response {
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Response::Error {
+            message: message.into(),
+        }
+    }
+}
+
+/// The path of the control socket. SKETCHOVER_SOCK wins if set, otherwise
+/// the socket lives in $XDG_RUNTIME_DIR/sketchover/ and is named after the
+/// wayland display, so one sketchover per compositor can be controlled.
+pub fn socket_path() -> Result<PathBuf, Box<dyn error::Error>> {
+    runtime_socket(SOCKET_ENV, "")
+}
+
+/// The path of the lua repl socket, next to the control socket unless
+/// SKETCHOVER_REPL_SOCK is set
+pub fn repl_socket_path() -> Result<PathBuf, Box<dyn error::Error>> {
+    runtime_socket(REPL_SOCKET_ENV, "-repl")
+}
+
+fn runtime_socket(var: &str, suffix: &str) -> Result<PathBuf, Box<dyn error::Error>> {
+    if let Some(path) = env::var_os(var) {
+        return Ok(PathBuf::from(path));
+    }
+    let display = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_owned());
+    let xdg_dirs = BaseDirectories::with_prefix("sketchover")?;
+    let path = xdg_dirs.place_runtime_file(format!("{display}{suffix}.sock"))?;
+    Ok(path)
+}
+
+/// What a chunk sent to the repl socket evaluated to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Eval {
+    /// The values the chunk returned, converted with tostring
+    Ok {
+        values: Vec<String>,
+    },
+    /// The chunk isn't finished, send it again with the next line
+    Incomplete,
+    Error {
+        message: String,
+    },
+}
+
+/// Send a request to a running sketchover and wait for the response
+pub fn send(path: &Path, request: &Request) -> Result<Response, Box<dyn error::Error>> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+// Removes the socket file when the event loop drops the source
+struct Listener {
+    inner: UnixListener,
+    path: PathBuf,
+}
+
+impl std::os::fd::AsFd for Listener {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        // Someone is already listening, don't steal their socket
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AddrInUse,
+                format!("{} is already in use", path.display()),
+            ));
+        }
+        // A left over from a sketchover that didn't exit cleanly
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Insert the control socket into the event loop. Requests are handled
+/// by the runtime, anything it doesn't know about is passed to
+/// Events::request.
+pub fn listen<'l, D: Events + 'static>(
+    handle: &LoopHandle<'l, Runtime<D>>,
+    path: PathBuf,
+) -> Result<(), Box<dyn error::Error>> {
+    let listener = Listener {
+        inner: bind(&path)?,
+        path,
+    };
+    let conn_handle = handle.clone();
+    handle.insert_source(
+        Generic::new(listener, Interest::READ, Mode::Level),
+        move |_, listener, _rt| {
+            loop {
+                match listener.inner.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = insert_connection(&conn_handle, stream) {
+                            log::warn!("Couldn't accept ipc connection: {e}");
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(PostAction::Continue)
+        },
+    )?;
+    Ok(())
+}
+
+fn insert_connection<'l, D: Events + 'static>(
+    handle: &LoopHandle<'l, Runtime<D>>,
+    stream: UnixStream,
+) -> Result<(), Box<dyn error::Error>> {
+    stream.set_nonblocking(true)?;
+    let mut buf = Vec::new();
+    handle.insert_source(
+        Generic::new(stream, Interest::READ, Mode::Level),
+        move |_, stream, rt| {
+            let mut chunk = [0; 1024];
+            let eof = loop {
+                match (&**stream).read(&mut chunk) {
+                    Ok(0) => break true,
+                    Ok(n) => {
+                        buf.extend_from_slice(&chunk[..n]);
+                        // only the first line is read
+                        if chunk[..n].contains(&b'\n') {
+                            break false;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break false,
+                    Err(e) => {
+                        log::warn!("Couldn't read ipc request: {e}");
+                        return Ok(PostAction::Remove);
+                    }
+                }
+                if too_long(&buf) {
+                    log::warn!("Dropped an ipc request longer than {MAX_LINE} bytes");
+                    return Ok(PostAction::Remove);
+                }
+            };
+            let line = match buf.iter().position(|b| *b == b'\n') {
+                Some(end) => &buf[..end],
+                None if eof => &buf[..],
+                None => return Ok(PostAction::Continue),
+            };
+            let sent = match rt.plugin_request(line) {
+                Some(response) => respond(stream, &response),
+                None => {
+                    let response = match serde_json::from_slice::<Request>(line) {
+                        Ok(request) => rt.handle_request(request),
+                        Err(e) => Response::error(format!("Invalid request: {e}")),
+                    };
+                    respond(stream, &response)
+                }
+            };
+            if let Err(e) = sent {
+                log::warn!("Couldn't send ipc response: {e}");
+            }
+            Ok(PostAction::Remove)
+        },
+    )?;
+    Ok(())
+}
+
+/// If the line at the end of buf, that hasn't ended yet, is longer than
+/// MAX_LINE
+fn too_long(buf: &[u8]) -> bool {
+    let start = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    buf.len() - start > MAX_LINE
+}
+
+fn respond<T: Serialize>(
+    mut stream: &UnixStream,
+    response: &T,
+) -> Result<(), Box<dyn error::Error>> {
+    stream.set_nonblocking(false)?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    let mut line = serde_json::to_string(response)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Insert a repl socket into the event loop. Unlike the control socket
+/// the connections stay open, every line is a json string with a lua
+/// chunk that is answered with the Eval from eval.
+pub fn listen_repl<'l, D, F>(
+    handle: &LoopHandle<'l, Runtime<D>>,
+    path: PathBuf,
+    eval: F,
+) -> Result<(), Box<dyn error::Error>>
+where
+    D: Events + 'static,
+    F: Fn(&mut Runtime<D>, &str) -> Eval + Clone + 'l,
+{
+    let listener = Listener {
+        inner: bind(&path)?,
+        path,
+    };
+    // anyone who can connect can run lua as us
+    fs::set_permissions(&listener.path, Permissions::from_mode(0o600))?;
+    let conn_handle = handle.clone();
+    handle.insert_source(
+        Generic::new(listener, Interest::READ, Mode::Level),
+        move |_, listener, _rt| {
+            loop {
+                match listener.inner.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = insert_repl(&conn_handle, stream, eval.clone()) {
+                            log::warn!("Couldn't accept repl connection: {e}");
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(PostAction::Continue)
+        },
+    )?;
+    Ok(())
+}
+
+fn insert_repl<'l, D, F>(
+    handle: &LoopHandle<'l, Runtime<D>>,
+    stream: UnixStream,
+    eval: F,
+) -> Result<(), Box<dyn error::Error>>
+where
+    D: Events + 'static,
+    F: Fn(&mut Runtime<D>, &str) -> Eval + 'l,
+{
+    stream.set_nonblocking(true)?;
+    let mut buf = Vec::new();
+    handle.insert_source(
+        Generic::new(stream, Interest::READ, Mode::Level),
+        move |_, stream, rt| {
+            let mut chunk = [0; 1024];
+            let eof = loop {
+                match (&**stream).read(&mut chunk) {
+                    Ok(0) => break true,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break false,
+                    Err(e) => {
+                        log::warn!("Couldn't read from the repl: {e}");
+                        return Ok(PostAction::Remove);
+                    }
+                }
+                if too_long(&buf) {
+                    log::warn!("Dropped a repl sending a line longer than {MAX_LINE} bytes");
+                    return Ok(PostAction::Remove);
+                }
+            };
+            while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=end).collect();
+                let result = match serde_json::from_slice::<String>(&line) {
+                    Ok(code) => eval(rt, &code),
+                    Err(e) => Eval::Error {
+                        message: format!("Invalid chunk: {e}"),
+                    },
+                };
+                let sent =
+                    respond(stream, &result).and_then(|()| Ok(stream.set_nonblocking(true)?));
+                if let Err(e) = sent {
+                    log::warn!("Couldn't answer the repl: {e}");
+                    return Ok(PostAction::Remove);
+                }
+            }
+            if eof {
+                return Ok(PostAction::Remove);
+            }
+            Ok(PostAction::Continue)
+        },
+    )?;
+    Ok(())
+}
+
+/// A connection to the repl socket of a running sketchover
+pub struct Repl {
+    stream: BufReader<UnixStream>,
+}
+
+impl Repl {
+    pub fn connect(path: &Path) -> Result<Self, Box<dyn error::Error>> {
+        Ok(Repl {
+            stream: BufReader::new(UnixStream::connect(path)?),
+        })
+    }
+
+    /// Evaluate code in the lua state of sketchover
+    pub fn eval(&mut self, code: &str) -> Result<Eval, Box<dyn error::Error>> {
+        let mut line = serde_json::to_string(code)?;
+        line.push('\n');
+        self.stream.get_mut().write_all(line.as_bytes())?;
+        let mut response = String::new();
+        if self.stream.read_line(&mut response)? == 0 {
+            return Err("sketchover closed the connection".into());
+        }
+        Ok(serde_json::from_str(&response)?)
+    }
+}
+
+impl<D: Events + 'static> Runtime<D> {
+    /// Run a request against the runtime
+    pub fn handle_request(&mut self, request: Request) -> Response {
+        match request {
+            Request::Clear { output } => match self.locate_output(output) {
+                Some(output) => {
+                    output.clear();
+                    Response::Ok
+                }
+                None => no_output(output),
+            },
+            Request::Undo { output } => match self.locate_output(output) {
+                Some(output) => {
+                    output.undo();
+                    Response::Ok
+                }
+                None => no_output(output),
+            },
+            Request::Pause { output } => match self.locate_output_idx(output) {
+                Some(idx) => {
+                    self.set_pause(true, idx);
+                    Response::Ok
+                }
+                None => no_output(output),
+            },
+            Request::Unpause { output } => match self.locate_output_idx(output) {
+                Some(idx) => {
+                    self.set_pause(false, idx);
+                    Response::Ok
+                }
+                None => no_output(output),
+            },
+            Request::Save { output: id, path } => {
+                let path = path.unwrap_or_else(|| PathBuf::from("sketchover"));
+                match self.locate_output(id) {
+                    Some(output) => match output.save(path) {
+                        Ok(()) => Response::Ok,
+                        Err(e) => Response::error(format!("Couldn't save output: {e}")),
+                    },
+                    None => no_output(id),
+                }
+            }
+            Request::Passthrough { enable } => {
+                let enable = enable.unwrap_or(!self.passthrough());
+                self.set_passthrough(enable);
+                Response::Ok
+            }
+            Request::Snap { enable } => {
+                let snap = *self.snap();
+                self.set_snap(Snap {
+                    enabled: enable.unwrap_or(!snap.enabled),
+                    ..snap
+                });
+                Response::Ok
+            }
+            Request::Quit => {
+                self.exit();
+                Response::Ok
+            }
+            Request::GetOutputs => Response::Outputs {
+                outputs: self
+                    .outputs()
+                    .iter()
+                    .map(|o| OutputStatus {
+                        id: o.info.id,
+                        name: o.info.name.clone(),
+                        make: o.info.make.clone(),
+                        model: o.info.model.clone(),
+                        width: o.width,
+                        height: o.height,
+                        paused: o.screencopy.is_some(),
+                        draws: o.draws.len(),
+                        board: o.boards.name().to_owned(),
+                        boards: o.boards.names().map(str::to_owned).collect(),
+                        background: o.background,
+                    })
+                    .collect(),
+            },
+            Request::NewBoard { output, name } => self.board_request(output, BoardOp::New(name)),
+            Request::NextBoard { output } => self.board_request(output, BoardOp::Next),
+            Request::PrevBoard { output } => self.board_request(output, BoardOp::Prev),
+            Request::DeleteBoard { output } => self.board_request(output, BoardOp::Delete),
+            Request::SelectBoard { output, name } => {
+                self.board_request(output, BoardOp::Select(name))
+            }
+            Request::SetBackground {
+                output: id,
+                mode,
+                spacing,
+                color,
+            } => {
+                let color = match color.as_deref().map(parse_solid).transpose() {
+                    Ok(color) => color,
+                    Err(e) => return Response::error(format!("Bad color: {e}")),
+                };
+                match self.locate_output(id) {
+                    Some(output) => match output.background.apply(&Change {
+                        mode,
+                        spacing,
+                        color,
+                    }) {
+                        Ok(()) => Response::Ok,
+                        Err(e) => Response::error(e),
+                    },
+                    None => no_output(id),
+                }
+            }
+            request @ (Request::SetColor { .. } | Request::SetTool { .. } | Request::Reload) => {
+                D::request(self, &request)
+            }
+        }
+    }
+
+    fn board_request(&mut self, id: Option<u32>, op: BoardOp) -> Response {
+        match self.locate_output_idx(id) {
+            Some(idx) => match self.board(idx, op) {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::error(e),
+            },
+            None => no_output(id),
+        }
+    }
+}
+
+fn no_output(id: Option<u32>) -> Response {
+    match id {
+        Some(id) => Response::error(format!("No output with id {id}")),
+        None => Response::error("No active output"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{too_long, Request, Response, MAX_LINE};
+    use crate::background::Mode;
+
+    #[test]
+    fn line_length() {
+        let mut buf = vec![b'x'; MAX_LINE];
+        assert!(!too_long(&buf));
+        buf.push(b'x');
+        assert!(too_long(&buf));
+        // only the line that hasn't ended counts
+        buf.push(b'\n');
+        buf.push(b'x');
+        assert!(!too_long(&buf));
+    }
+
+    #[test]
+    fn request_from_json() {
+        let req: Request = serde_json::from_str(r#"{"command":"clear"}"#).unwrap();
+        assert_eq!(req, Request::Clear { output: None });
+
+        let req: Request =
+            serde_json::from_str(r##"{"command":"set_color","color":"#ff0000ff"}"##).unwrap();
+        assert_eq!(
+            req,
+            Request::SetColor {
+                color: "#ff0000ff".to_owned()
+            }
+        );
+
+        let req: Request = serde_json::from_str(r#"{"command":"pause","output":3}"#).unwrap();
+        assert_eq!(req, Request::Pause { output: Some(3) });
+
+        let req: Request =
+            serde_json::from_str(r#"{"command":"new_board","name":"notes"}"#).unwrap();
+        assert_eq!(
+            req,
+            Request::NewBoard {
+                output: None,
+                name: Some("notes".to_owned())
+            }
+        );
+
+        let req: Request =
+            serde_json::from_str(r#"{"command":"set_background","mode":"grid","spacing":16}"#)
+                .unwrap();
+        assert_eq!(
+            req,
+            Request::SetBackground {
+                output: None,
+                mode: Some(Mode::Grid),
+                spacing: Some(16.),
+                color: None,
+            }
+        );
+    }
+
+    #[test]
+    fn response_serialize_deserialize() {
+        let pre = Response::error("No active output");
+        let j = serde_json::to_string(&pre).unwrap();
+        let post: Response = serde_json::from_str(&j).unwrap();
+        assert!(pre == post)
+    }
+}
@@ -1,8 +1,17 @@
-// pub mod config;
+pub mod background;
+pub mod board;
+pub mod config;
 // can we simplify these?
+pub mod headless;
+pub mod ipc;
 pub mod keymap;
+pub mod luatypes;
+#[cfg(test)]
+mod mock_compositor;
 pub mod mousemap;
 pub mod pause;
+pub mod plugin;
+pub mod render;
 
 pub mod runtime;
 
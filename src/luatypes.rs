@@ -0,0 +1,424 @@
+// LuaCATS annotations for lua-language-server. The methods and fields
+// of a userdata are collected by calling the same add_methods and
+// add_fields that register them with lua, so the annotations can't
+// drift from the bindings. The lua types are guessed by parsing the
+// std::any::type_name of the arguments and return values, which isn't
+// a stable format, so a type that isn't recognised becomes any. The
+// closures don't have names for their arguments, those are given with
+// params.
+
+use std::any::type_name;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use mlua::{
+    AnyUserData, FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Lua, UserData, UserDataFields,
+    UserDataMethods,
+};
+
+#[derive(Default)]
+pub struct LuaTypes {
+    classes: Vec<Class>,
+    /// Rust type name to the lua class name
+    names: HashMap<&'static str, String>,
+}
+
+struct Class {
+    name: String,
+    members: Vec<Member>,
+}
+
+enum Member {
+    Field {
+        name: String,
+        ty: &'static str,
+    },
+    Function {
+        name: String,
+        method: bool,
+        params: Vec<String>,
+        args: &'static str,
+        ret: &'static str,
+    },
+}
+
+impl LuaTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Annotate T as the class name
+    pub fn class<T: UserData + 'static>(&mut self, name: &str) -> &mut Self {
+        let mut collector = Collector {
+            members: Vec::new(),
+        };
+        T::add_fields(&mut collector);
+        T::add_methods(&mut collector);
+        self.names.insert(type_name::<T>(), name.to_owned());
+        self.classes.push(Class {
+            name: name.to_owned(),
+            members: collector.members,
+        });
+        self
+    }
+
+    /// Name the parameters of the functions of the last class, the
+    /// parameters without a name are called arg1, arg2 and so on.
+    pub fn params(&mut self, functions: &[(&str, &[&str])]) -> &mut Self {
+        let Some(class) = self.classes.last_mut() else {
+            return self;
+        };
+        for member in class.members.iter_mut() {
+            if let Member::Function { name, params, .. } = member {
+                if let Some((_, names)) = functions.iter().find(|(f, _)| f == name) {
+                    *params = names.iter().map(|&n| n.to_owned()).collect();
+                }
+            }
+        }
+        self
+    }
+
+    /// A meta file for module, require(module) returns an instance of
+    /// the class module_class.
+    pub fn render(&self, module: &str, module_class: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "---@meta {module}");
+        let _ = writeln!(
+            out,
+            "-- Generated by sketchover --emit-lua-types, don't edit"
+        );
+        for class in self.classes.iter() {
+            let _ = writeln!(out, "\n---@class {}", class.name);
+            for member in class.members.iter() {
+                let _ = match member {
+                    Member::Field { name, ty } => {
+                        writeln!(out, "---@field {name} {}", self.lua_type(ty))
+                    }
+                    Member::Function {
+                        name,
+                        method,
+                        params,
+                        args,
+                        ret,
+                    } => writeln!(
+                        out,
+                        "---@field {name} {}",
+                        self.function(&class.name, *method, params, args, ret)
+                    ),
+                };
+            }
+        }
+        let _ = writeln!(out, "\n---@type {module_class}");
+        let _ = writeln!(out, "local {module}");
+        let _ = writeln!(out, "return {module}");
+        out
+    }
+
+    fn function(
+        &self,
+        class: &str,
+        method: bool,
+        names: &[String],
+        args: &str,
+        ret: &str,
+    ) -> String {
+        let mut params = Vec::new();
+        if method {
+            params.push(format!("self: {class}"));
+        }
+        for (i, arg) in split_multi(args).iter().enumerate() {
+            let name = names
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("arg{}", i + 1));
+            match generic(arg) {
+                ("Option", Some(inner)) => {
+                    params.push(format!("{name}?: {}", self.lua_type(inner)))
+                }
+                // a Value takes nil as well, so it can always be left out
+                ("Value", _) => params.push(format!("{name}?: any")),
+                _ => params.push(format!("{name}: {}", self.lua_type(arg))),
+            }
+        }
+        let rets: Vec<_> = split_multi(ret).iter().map(|r| self.lua_type(r)).collect();
+        if rets.is_empty() {
+            format!("fun({})", params.join(", "))
+        } else {
+            format!("fun({}): {}", params.join(", "), rets.join(", "))
+        }
+    }
+
+    fn lua_type(&self, rust: &str) -> String {
+        if let Some(name) = self.names.get(rust) {
+            return name.clone();
+        }
+        let rust = rust.trim_start_matches('&');
+        match generic(rust) {
+            ("Option", Some(inner)) => format!("{}?", self.lua_type(inner)),
+            ("Vec", Some(inner)) => format!("{}[]", self.lua_type(inner)),
+            ("Variadic", Some(inner)) => format!("{}...", self.lua_type(inner)),
+            ("()", None) => "nil".to_owned(),
+            ("String" | "str" | "PathBuf", None) => "string".to_owned(),
+            ("bool", None) => "boolean".to_owned(),
+            ("u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64", None) => {
+                "integer".to_owned()
+            }
+            ("f32" | "f64", None) => "number".to_owned(),
+            ("Table", None) => "table".to_owned(),
+            ("Function", None) => "function".to_owned(),
+            ("AnyUserData", None) => "userdata".to_owned(),
+            _ => "any".to_owned(),
+        }
+    }
+}
+
+/// The last path segment of the type and what's inside of the <>,
+/// without the lifetime of types like Table<'_>
+fn generic(rust: &str) -> (&str, Option<&str>) {
+    let (head, inner) = match rust.split_once('<') {
+        Some((head, rest)) => (head, rest.strip_suffix('>')),
+        None => (rust, None),
+    };
+    let inner = inner
+        .map(|i| {
+            i.strip_prefix("'_")
+                .map_or(i, |i| i.trim_start_matches([',', ' ']))
+        })
+        .filter(|i| !i.is_empty());
+    (head.rsplit("::").next().unwrap_or(head), inner)
+}
+
+/// The values of a multi value, () is no values and a tuple is one
+/// value per element.
+fn split_multi(rust: &str) -> Vec<&str> {
+    if rust == "()" {
+        return Vec::new();
+    }
+    let Some(inner) = rust.strip_prefix('(').and_then(|r| r.strip_suffix(')')) else {
+        return vec![rust];
+    };
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+struct Collector {
+    members: Vec<Member>,
+}
+
+impl Collector {
+    fn field<V>(&mut self, name: impl AsRef<str>) {
+        self.members.push(Member::Field {
+            name: name.as_ref().to_owned(),
+            ty: type_name::<V>(),
+        });
+    }
+
+    fn function<A, R>(&mut self, name: impl AsRef<str>, method: bool) {
+        self.members.push(Member::Function {
+            name: name.as_ref().to_owned(),
+            method,
+            params: Vec::new(),
+            args: type_name::<A>(),
+            ret: type_name::<R>(),
+        });
+    }
+}
+
+// Meta methods and setters aren't something you call by name, so they
+// are left out.
+impl<'lua, T> UserDataMethods<'lua, T> for Collector {
+    fn add_method<M, A, R>(&mut self, name: impl AsRef<str>, _method: M)
+    where
+        M: Fn(&'lua Lua, &T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.function::<A, R>(name, true);
+    }
+
+    fn add_method_mut<M, A, R>(&mut self, name: impl AsRef<str>, _method: M)
+    where
+        M: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.function::<A, R>(name, true);
+    }
+
+    fn add_function<F, A, R>(&mut self, name: impl AsRef<str>, _function: F)
+    where
+        F: Fn(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.function::<A, R>(name, false);
+    }
+
+    fn add_function_mut<F, A, R>(&mut self, name: impl AsRef<str>, _function: F)
+    where
+        F: FnMut(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.function::<A, R>(name, false);
+    }
+
+    fn add_meta_method<M, A, R>(&mut self, _name: impl AsRef<str>, _method: M)
+    where
+        M: Fn(&'lua Lua, &T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+    }
+
+    fn add_meta_method_mut<M, A, R>(&mut self, _name: impl AsRef<str>, _method: M)
+    where
+        M: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+    }
+
+    fn add_meta_function<F, A, R>(&mut self, _name: impl AsRef<str>, _function: F)
+    where
+        F: Fn(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+    }
+
+    fn add_meta_function_mut<F, A, R>(&mut self, _name: impl AsRef<str>, _function: F)
+    where
+        F: FnMut(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+    }
+}
+
+impl<'lua, T> UserDataFields<'lua, T> for Collector {
+    fn add_field<V>(&mut self, name: impl AsRef<str>, _value: V)
+    where
+        V: IntoLua<'lua> + Clone + 'static,
+    {
+        self.field::<V>(name);
+    }
+
+    fn add_field_method_get<M, R>(&mut self, name: impl AsRef<str>, _method: M)
+    where
+        M: Fn(&'lua Lua, &T) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+        self.field::<R>(name);
+    }
+
+    fn add_field_method_set<M, A>(&mut self, _name: impl AsRef<str>, _method: M)
+    where
+        M: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<()> + 'static,
+        A: FromLua<'lua>,
+    {
+    }
+
+    fn add_field_function_get<F, R>(&mut self, name: impl AsRef<str>, _function: F)
+    where
+        F: Fn(&'lua Lua, AnyUserData<'lua>) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+        self.field::<R>(name);
+    }
+
+    fn add_field_function_set<F, A>(&mut self, _name: impl AsRef<str>, _function: F)
+    where
+        F: FnMut(&'lua Lua, AnyUserData<'lua>, A) -> mlua::Result<()> + 'static,
+        A: FromLua<'lua>,
+    {
+    }
+
+    fn add_meta_field<V>(&mut self, _name: impl AsRef<str>, _value: V)
+    where
+        V: IntoLua<'lua> + Clone + 'static,
+    {
+    }
+
+    fn add_meta_field_with<F, R>(&mut self, _name: impl AsRef<str>, _f: F)
+    where
+        F: Fn(&'lua Lua) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::{Function, Table, UserData, UserDataFields, UserDataMethods, Value};
+
+    use super::{split_multi, LuaTypes};
+
+    struct Point;
+
+    impl UserData for Point {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field_method_get("x", |_, _| Ok(1.0f64));
+            fields.add_field_method_get("name", |_, _| Ok(Some("p".to_owned())));
+        }
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("moved", |_, _, (_dx, _dy): (f64, Option<f64>)| Ok(Point));
+            methods.add_method("check", |_, _, _: Value| Ok((Some(true), None::<String>)));
+            methods.add_function("each", |_, _: (Table, Function)| Ok(()));
+            methods.add_meta_method("__tostring", |_, _, ()| Ok("point"));
+        }
+    }
+
+    #[test]
+    fn render() {
+        let stubs = LuaTypes::new()
+            .class::<Point>("Point")
+            .params(&[("moved", &["dx", "dy"])])
+            .render("points", "Point");
+        assert_eq!(
+            stubs,
+            "---@meta points
+-- Generated by sketchover --emit-lua-types, don't edit
+
+---@class Point
+---@field x number
+---@field name string?
+---@field moved fun(self: Point, dx: number, dy?: number): Point
+---@field check fun(self: Point, arg1?: any): boolean?, string?
+---@field each fun(arg1: table, arg2: function)
+
+---@type Point
+local points
+return points
+"
+        );
+    }
+
+    #[test]
+    fn multi_values() {
+        assert!(split_multi("()").is_empty());
+        assert_eq!(split_multi("u32"), ["u32"]);
+        assert_eq!(
+            split_multi("(core::option::Option<(u32, u32)>, alloc::string::String)"),
+            ["core::option::Option<(u32, u32)>", "alloc::string::String"]
+        );
+    }
+}
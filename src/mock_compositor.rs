@@ -0,0 +1,915 @@
+// A tiny wayland compositor for the tests. It runs on its own thread
+// and implements just enough of the protocols sketchover binds to get
+// outputs, layer surfaces, a seat and screencopy going. Input is
+// injected with the methods on MockCompositor.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use wayland_client::Connection;
+use wayland_protocols::xdg::xdg_output::zv1::server::{zxdg_output_manager_v1, zxdg_output_v1};
+use wayland_protocols_wlr::layer_shell::v1::server::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
+use wayland_protocols_wlr::screencopy::v1::server::{
+    zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
+};
+use wayland_server::backend::{ClientData, ClientId, DisconnectReason, GlobalId};
+use wayland_server::protocol::{
+    wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_pointer, wl_region, wl_seat,
+    wl_shm, wl_shm_pool, wl_surface,
+};
+use wayland_server::{
+    Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+use xkbcommon::xkb;
+
+pub const BTN_LEFT: u32 = 0x110;
+pub const BTN_RIGHT: u32 = 0x111;
+pub const KEY_U: u32 = 22;
+pub const KEY_ESC: u32 = 1;
+/// Modifier masks of the us keymap
+pub const SHIFT: u32 = 1;
+
+/// What the compositor knows about a layer surface
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerInfo {
+    pub output: Option<String>,
+    pub keyboard_interactivity: u32,
+    /// None means the whole surface takes input
+    pub input_region: Option<Rects>,
+}
+
+enum Command {
+    AddOutput {
+        name: String,
+        width: i32,
+        height: i32,
+    },
+    RemoveOutput(String),
+    Enter {
+        output: String,
+        pos: (f64, f64),
+    },
+    Motion((f64, f64)),
+    Button {
+        button: u32,
+        pressed: bool,
+    },
+    Scroll {
+        value: f64,
+        discrete: i32,
+    },
+    ScrollStop,
+    Modifiers {
+        depressed: u32,
+    },
+    Key {
+        key: u32,
+        pressed: bool,
+    },
+    Layers(Sender<Vec<LayerInfo>>),
+    Quit,
+}
+
+pub struct MockCompositor {
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockCompositor {
+    /// Start the compositor, the returned connection is the client side.
+    pub fn new() -> (Self, Connection) {
+        let (client, server) = UnixStream::pair().expect("Couldn't create a socket pair");
+        let (tx, rx) = mpsc::channel();
+        let thread = thread::spawn(move || serve(server, rx));
+        let conn = Connection::from_socket(client).expect("Couldn't connect to mock compositor");
+        (
+            MockCompositor {
+                commands: tx,
+                thread: Some(thread),
+            },
+            conn,
+        )
+    }
+
+    fn send(&self, command: Command) {
+        self.commands
+            .send(command)
+            .expect("Mock compositor has stopped");
+    }
+
+    pub fn add_output(&self, name: &str, width: i32, height: i32) {
+        self.send(Command::AddOutput {
+            name: name.to_owned(),
+            width,
+            height,
+        });
+    }
+
+    pub fn remove_output(&self, name: &str) {
+        self.send(Command::RemoveOutput(name.to_owned()));
+    }
+
+    /// Move pointer and keyboard focus to the layer surface on output
+    pub fn enter(&self, output: &str, pos: (f64, f64)) {
+        self.send(Command::Enter {
+            output: output.to_owned(),
+            pos,
+        });
+    }
+
+    pub fn motion(&self, pos: (f64, f64)) {
+        self.send(Command::Motion(pos));
+    }
+
+    pub fn button(&self, button: u32, pressed: bool) {
+        self.send(Command::Button { button, pressed });
+    }
+
+    /// Vertical scroll with a wheel, in steps
+    pub fn wheel(&self, steps: i32) {
+        self.send(Command::Scroll {
+            value: steps as f64 * 15.,
+            discrete: steps,
+        });
+    }
+
+    /// Vertical scroll with a touchpad, in pixels
+    pub fn smooth_scroll(&self, value: f64) {
+        self.send(Command::Scroll { value, discrete: 0 });
+    }
+
+    pub fn scroll_stop(&self) {
+        self.send(Command::ScrollStop);
+    }
+
+    /// key is an evdev keycode
+    pub fn key(&self, key: u32, pressed: bool) {
+        self.send(Command::Key { key, pressed });
+    }
+
+    pub fn modifiers(&self, depressed: u32) {
+        self.send(Command::Modifiers { depressed });
+    }
+
+    pub fn layers(&self) -> Vec<LayerInfo> {
+        let (tx, rx) = mpsc::channel();
+        self.send(Command::Layers(tx));
+        rx.recv().expect("Mock compositor has stopped")
+    }
+}
+
+impl Drop for MockCompositor {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(stream: UnixStream, commands: Receiver<Command>) {
+    let mut display = Display::<State>::new().expect("Couldn't create display");
+    let mut dh = display.handle();
+    dh.insert_client(stream, Arc::new(ClientState))
+        .expect("Couldn't add client");
+
+    let mut state = State::new(&dh);
+    loop {
+        match commands.recv_timeout(Duration::from_millis(1)) {
+            Ok(Command::Quit) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(command) => state.handle(&mut dh, command),
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if display.dispatch_clients(&mut state).is_err() {
+            break;
+        }
+        let _ = display.flush_clients();
+    }
+}
+
+struct ClientState;
+
+impl ClientData for ClientState {
+    fn initialized(&self, _client_id: ClientId) {}
+    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
+}
+
+struct Output {
+    name: String,
+    width: i32,
+    height: i32,
+    global: GlobalId,
+}
+
+struct Layer {
+    surface: wl_surface::WlSurface,
+    layer: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    output: Option<String>,
+    keyboard_interactivity: u32,
+    configured: bool,
+}
+
+#[derive(Default)]
+struct SurfaceData {
+    buffer: Option<wl_buffer::WlBuffer>,
+    pending_region: Option<Option<Rects>>,
+    input_region: Option<Rects>,
+}
+
+type Rects = Vec<(i32, i32, i32, i32)>;
+type RegionData = Mutex<Rects>;
+
+struct State {
+    outputs: Vec<Output>,
+    layers: Vec<Layer>,
+    pointers: Vec<wl_pointer::WlPointer>,
+    keyboards: Vec<wl_keyboard::WlKeyboard>,
+    focus: Option<wl_surface::WlSurface>,
+    keymap: (File, u32),
+    serial: u32,
+}
+
+impl State {
+    fn new(dh: &DisplayHandle) -> Self {
+        dh.create_global::<State, wl_compositor::WlCompositor, ()>(4, ());
+        dh.create_global::<State, wl_shm::WlShm, ()>(1, ());
+        dh.create_global::<State, wl_seat::WlSeat, ()>(7, ());
+        dh.create_global::<State, zxdg_output_manager_v1::ZxdgOutputManagerV1, ()>(3, ());
+        dh.create_global::<State, zwlr_layer_shell_v1::ZwlrLayerShellV1, ()>(4, ());
+        dh.create_global::<State, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()>(3, ());
+        State {
+            outputs: Vec::new(),
+            layers: Vec::new(),
+            pointers: Vec::new(),
+            keyboards: Vec::new(),
+            focus: None,
+            keymap: keymap_file(),
+            serial: 0,
+        }
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial += 1;
+        self.serial
+    }
+
+    fn output(&self, name: &str) -> Option<&Output> {
+        self.outputs.iter().find(|o| o.name == name)
+    }
+
+    fn handle(&mut self, dh: &mut DisplayHandle, command: Command) {
+        match command {
+            Command::AddOutput {
+                name,
+                width,
+                height,
+            } => {
+                let global =
+                    dh.create_global::<State, wl_output::WlOutput, String>(4, name.clone());
+                self.outputs.push(Output {
+                    name,
+                    width,
+                    height,
+                    global,
+                });
+            }
+            Command::RemoveOutput(name) => {
+                if let Some(idx) = self.outputs.iter().position(|o| o.name == name) {
+                    let output = self.outputs.remove(idx);
+                    dh.remove_global::<State>(output.global);
+                }
+            }
+            Command::Enter { output, pos } => {
+                let Some(surface) = self
+                    .layers
+                    .iter()
+                    .find(|l| l.output.as_deref() == Some(&output))
+                    .map(|l| l.surface.clone())
+                else {
+                    panic!("No layer surface on {output}");
+                };
+                if let Some(old) = self.focus.take() {
+                    let serial = self.next_serial();
+                    for pointer in self.pointers.iter() {
+                        pointer.leave(serial, &old);
+                        pointer.frame();
+                    }
+                    for keyboard in self.keyboards.iter() {
+                        keyboard.leave(serial, &old);
+                    }
+                }
+                let serial = self.next_serial();
+                for pointer in self.pointers.iter() {
+                    pointer.enter(serial, &surface, pos.0, pos.1);
+                    pointer.frame();
+                }
+                for keyboard in self.keyboards.iter() {
+                    keyboard.enter(serial, &surface, Vec::new());
+                }
+                self.focus = Some(surface);
+            }
+            Command::Motion(pos) => {
+                for pointer in self.pointers.iter() {
+                    pointer.motion(0, pos.0, pos.1);
+                    pointer.frame();
+                }
+            }
+            Command::Button { button, pressed } => {
+                let serial = self.next_serial();
+                let state = if pressed {
+                    wl_pointer::ButtonState::Pressed
+                } else {
+                    wl_pointer::ButtonState::Released
+                };
+                for pointer in self.pointers.iter() {
+                    pointer.button(serial, 0, button, state);
+                    pointer.frame();
+                }
+            }
+            Command::Scroll { value, discrete } => {
+                let vertical = wl_pointer::Axis::VerticalScroll;
+                for pointer in self.pointers.iter() {
+                    if discrete != 0 {
+                        pointer.axis_source(wl_pointer::AxisSource::Wheel);
+                        pointer.axis_discrete(vertical, discrete);
+                    } else {
+                        pointer.axis_source(wl_pointer::AxisSource::Finger);
+                    }
+                    pointer.axis(0, vertical, value);
+                    pointer.frame();
+                }
+            }
+            Command::ScrollStop => {
+                for pointer in self.pointers.iter() {
+                    pointer.axis_stop(0, wl_pointer::Axis::VerticalScroll);
+                    pointer.frame();
+                }
+            }
+            Command::Key { key, pressed } => {
+                let serial = self.next_serial();
+                let state = if pressed {
+                    wl_keyboard::KeyState::Pressed
+                } else {
+                    wl_keyboard::KeyState::Released
+                };
+                for keyboard in self.keyboards.iter() {
+                    keyboard.key(serial, 0, key, state);
+                }
+            }
+            Command::Modifiers { depressed } => {
+                let serial = self.next_serial();
+                for keyboard in self.keyboards.iter() {
+                    keyboard.modifiers(serial, depressed, 0, 0, 0);
+                }
+            }
+            Command::Layers(reply) => {
+                let layers = self
+                    .layers
+                    .iter()
+                    .map(|l| LayerInfo {
+                        output: l.output.clone(),
+                        keyboard_interactivity: l.keyboard_interactivity,
+                        input_region: surface_data(&l.surface).input_region.clone(),
+                    })
+                    .collect();
+                let _ = reply.send(layers);
+            }
+            Command::Quit => {}
+        }
+    }
+}
+
+fn surface_data(surface: &wl_surface::WlSurface) -> std::sync::MutexGuard<'_, SurfaceData> {
+    surface
+        .data::<Mutex<SurfaceData>>()
+        .expect("Surface without data")
+        .lock()
+        .unwrap()
+}
+
+// The keymap is sent as a file descriptor, so write it to a file
+// that is unlinked right away.
+fn keymap_file() -> (File, u32) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    let keymap = xkb::Keymap::new_from_names(
+        &context,
+        "evdev",
+        "pc105",
+        "us",
+        "",
+        None,
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+    .expect("Couldn't compile keymap");
+    let mut keymap = keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
+    keymap.push('\0');
+
+    let path = std::env::temp_dir().join(format!(
+        "sketchover-keymap-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .expect("Couldn't create keymap file");
+    file.write_all(keymap.as_bytes())
+        .expect("Couldn't write keymap");
+    let _ = fs::remove_file(&path);
+    (file, keymap.len() as u32)
+}
+
+impl GlobalDispatch<wl_compositor::WlCompositor, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_compositor::WlCompositor>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_compositor::WlCompositor,
+        request: wl_compositor::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_compositor::Request::CreateSurface { id } => {
+                data_init.init(id, Mutex::new(SurfaceData::default()));
+            }
+            wl_compositor::Request::CreateRegion { id } => {
+                data_init.init(id, RegionData::default());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_surface::WlSurface, Mutex<SurfaceData>> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wl_surface::WlSurface,
+        request: wl_surface::Request,
+        data: &Mutex<SurfaceData>,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_surface::Request::Attach { buffer, .. } => {
+                data.lock().unwrap().buffer = buffer;
+            }
+            wl_surface::Request::Frame { callback } => {
+                // Frames are never done, the tests don't need a redraw
+                data_init.init(callback, ());
+            }
+            wl_surface::Request::SetInputRegion { region } => {
+                let region =
+                    region.map(|r| r.data::<RegionData>().unwrap().lock().unwrap().clone());
+                data.lock().unwrap().pending_region = Some(region);
+            }
+            wl_surface::Request::Commit => {
+                let mut data = data.lock().unwrap();
+                // We never read the buffer, so it can be reused right away
+                if let Some(buffer) = data.buffer.take() {
+                    buffer.release();
+                }
+                if let Some(region) = data.pending_region.take() {
+                    data.input_region = region;
+                }
+                drop(data);
+
+                if let Some(layer) = state
+                    .layers
+                    .iter_mut()
+                    .find(|l| &l.surface == resource && !l.configured)
+                {
+                    layer.configured = true;
+                    let (width, height) = layer
+                        .output
+                        .as_deref()
+                        .and_then(|name| state.outputs.iter().find(|o| o.name == name))
+                        .map_or((0, 0), |o| (o.width as u32, o.height as u32));
+                    state.serial += 1;
+                    layer.layer.configure(state.serial, width, height);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_region::WlRegion, RegionData> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_region::WlRegion,
+        request: wl_region::Request,
+        data: &RegionData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_region::Request::Add {
+            x,
+            y,
+            width,
+            height,
+        } = request
+        {
+            data.lock().unwrap().push((x, y, width, height));
+        }
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_callback::WlCallback,
+        _request: wl_callback::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<wl_shm::WlShm, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_shm::WlShm>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let shm = data_init.init(resource, ());
+        shm.format(wl_shm::Format::Argb8888);
+        shm.format(wl_shm::Format::Xrgb8888);
+    }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_shm::WlShm,
+        request: wl_shm::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm::Request::CreatePool { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_shm_pool::WlShmPool,
+        request: wl_shm_pool::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm_pool::Request::CreateBuffer { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_buffer::WlBuffer,
+        _request: wl_buffer::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<wl_output::WlOutput, String> for State {
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_output::WlOutput>,
+        name: &String,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let wl_output = data_init.init(resource, name.clone());
+        let Some(output) = state.output(name) else {
+            return;
+        };
+        wl_output.geometry(
+            0,
+            0,
+            output.width / 4,
+            output.height / 4,
+            wl_output::Subpixel::Unknown,
+            "mock".to_owned(),
+            name.clone(),
+            wl_output::Transform::Normal,
+        );
+        wl_output.mode(wl_output::Mode::Current, output.width, output.height, 60000);
+        wl_output.scale(1);
+        wl_output.name(name.clone());
+        wl_output.done();
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, String> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_output::WlOutput,
+        _request: wl_output::Request,
+        _data: &String,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<zxdg_output_manager_v1::ZxdgOutputManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zxdg_output_manager_v1::ZxdgOutputManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<zxdg_output_manager_v1::ZxdgOutputManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zxdg_output_manager_v1::ZxdgOutputManagerV1,
+        request: zxdg_output_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zxdg_output_manager_v1::Request::GetXdgOutput {
+            id,
+            output: wl_output,
+        } = request
+        {
+            let xdg_output = data_init.init(id, ());
+            let Some(output) = wl_output
+                .data::<String>()
+                .and_then(|name| state.output(name))
+            else {
+                return;
+            };
+            xdg_output.logical_position(0, 0);
+            xdg_output.logical_size(output.width, output.height);
+            xdg_output.name(output.name.clone());
+            // Since version 3 the xdg output is done with the wl_output
+            wl_output.done();
+        }
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zxdg_output_v1::ZxdgOutputV1,
+        _request: zxdg_output_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<wl_seat::WlSeat, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_seat::WlSeat>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let seat = data_init.init(resource, ());
+        seat.capabilities(wl_seat::Capability::Pointer | wl_seat::Capability::Keyboard);
+        if seat.version() >= 2 {
+            seat.name("seat0".to_owned());
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wl_seat::WlSeat,
+        request: wl_seat::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_seat::Request::GetPointer { id } => {
+                let pointer = data_init.init(id, ());
+                state.pointers.push(pointer);
+            }
+            wl_seat::Request::GetKeyboard { id } => {
+                let keyboard = data_init.init(id, ());
+                let (ref file, size) = state.keymap;
+                keyboard.keymap(wl_keyboard::KeymapFormat::XkbV1, file.as_fd(), size);
+                if keyboard.version() >= 4 {
+                    keyboard.repeat_info(0, 0);
+                }
+                state.keyboards.push(keyboard);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_pointer::WlPointer,
+        _request: wl_pointer::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_keyboard::WlKeyboard,
+        _request: wl_keyboard::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_layer_shell_v1::ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_layer_shell_v1::Request::GetLayerSurface {
+            id,
+            surface,
+            output,
+            ..
+        } = request
+        {
+            let layer = data_init.init(id, ());
+            state.layers.push(Layer {
+                surface,
+                layer,
+                output: output.and_then(|o| o.data::<String>().cloned()),
+                keyboard_interactivity: 0,
+                configured: false,
+            });
+        }
+    }
+}
+
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        request: zwlr_layer_surface_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_layer_surface_v1::Request::SetKeyboardInteractivity {
+                keyboard_interactivity,
+            } => {
+                if let Some(layer) = state.layers.iter_mut().find(|l| &l.layer == resource) {
+                    layer.keyboard_interactivity = match keyboard_interactivity {
+                        WEnum::Value(value) => value as u32,
+                        WEnum::Unknown(value) => value,
+                    };
+                }
+            }
+            zwlr_layer_surface_v1::Request::Destroy => {
+                state.layers.retain(|l| &l.layer != resource);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl GlobalDispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for State {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_screencopy_manager_v1::Request::CaptureOutput { frame, output, .. } = request {
+            let frame = data_init.init(frame, ());
+            let Some(output) = output.data::<String>().and_then(|name| state.output(name)) else {
+                frame.failed();
+                return;
+            };
+            frame.buffer(
+                wl_shm::Format::Argb8888,
+                output.width as u32,
+                output.height as u32,
+                output.width as u32 * 4,
+            );
+            frame.buffer_done();
+        }
+    }
+}
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for State {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_screencopy_frame_v1::Request::Copy { .. } = request {
+            resource.flags(zwlr_screencopy_frame_v1::Flags::empty());
+            resource.ready(0, 0, 0);
+        }
+    }
+}
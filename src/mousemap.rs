@@ -7,8 +7,9 @@ use serde::{
     Deserialize, Deserializer, Serialize,
 };
 use smithay_client_toolkit::seat::keyboard::Modifiers;
+use smithay_client_toolkit::seat::pointer::AxisScroll;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum MouseEvent {
     BtnLeft,
     BtnRight,
@@ -21,9 +22,10 @@ pub enum MouseEvent {
     Raw(u32),
 }
 
+// Hash the button code, Raw(0x110) is the same button as BtnLeft
 impl Hash for MouseEvent {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        core::mem::discriminant(self).hash(state);
+        u32::from(self).hash(state);
     }
 }
 
@@ -74,7 +76,7 @@ pub struct MouseMap {
     pub modifier: Modifiers,
 }
 
-#[derive(Hash, Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[derive(Hash, Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy)]
 pub enum Mouse {
     ScrollUp,
     ScrollDown,
@@ -83,6 +85,80 @@ pub enum Mouse {
     Button(MouseEvent),
 }
 
+/// Turns axis events into scroll steps. A wheel sends discrete steps,
+/// touchpads send a stream of pixel deltas that are added up until they
+/// pass the threshold.
+#[derive(Debug)]
+pub struct Scroll {
+    threshold: f64,
+    horizontal: f64,
+    vertical: f64,
+}
+
+impl Scroll {
+    /// threshold is the distance in pixels of one step for smooth scrolling
+    pub fn new(threshold: f64) -> Self {
+        Scroll {
+            // a zero threshold would never stop stepping
+            threshold: threshold.max(1.),
+            horizontal: 0.,
+            vertical: 0.,
+        }
+    }
+
+    /// Feed the axis of a pointer frame, returns the steps scrolled
+    pub fn axis(&mut self, horizontal: &AxisScroll, vertical: &AxisScroll) -> Vec<Mouse> {
+        let mut steps = Vec::new();
+        Self::steps(
+            &mut self.horizontal,
+            self.threshold,
+            horizontal,
+            (Mouse::ScrollLeft, Mouse::ScrollRight),
+            &mut steps,
+        );
+        Self::steps(
+            &mut self.vertical,
+            self.threshold,
+            vertical,
+            (Mouse::ScrollUp, Mouse::ScrollDown),
+            &mut steps,
+        );
+        steps
+    }
+
+    fn steps(
+        acc: &mut f64,
+        threshold: f64,
+        axis: &AxisScroll,
+        (negative, positive): (Mouse, Mouse),
+        steps: &mut Vec<Mouse>,
+    ) {
+        // value120 needs wl_seat v8 and sctk only binds up to v7,
+        // so discrete is the best we get from a wheel.
+        if axis.discrete != 0 {
+            // the pixel value of a wheel click is just an estimate
+            *acc = 0.;
+            let direction = if axis.discrete < 0 {
+                negative
+            } else {
+                positive
+            };
+            for _ in 0..axis.discrete.unsigned_abs() {
+                steps.push(direction);
+            }
+        } else {
+            *acc += axis.absolute;
+            while acc.abs() >= threshold {
+                steps.push(if *acc < 0. { negative } else { positive });
+                *acc -= threshold.copysign(*acc);
+            }
+        }
+        if axis.stop {
+            *acc = 0.;
+        }
+    }
+}
+
 impl MouseMap {
     pub fn new(event: Mouse, modifier: Modifiers) -> Self {
         MouseMap { event, modifier }
@@ -192,13 +268,13 @@ impl<'de> Deserialize<'de> for MouseMap {
                         }
                     }
                 }
-                let event = event.ok_or_else(|| de::Error::missing_field("key"))?;
+                let event = event.ok_or_else(|| de::Error::missing_field("event"))?;
                 let modifier = modifier.ok_or_else(|| de::Error::missing_field("modifier"))?;
                 Ok(MouseMap { event, modifier })
             }
         }
 
-        const FIELDS: &[&str] = &["mouse", "modifier"];
+        const FIELDS: &[&str] = &["event", "modifier"];
         deserializer.deserialize_struct("MouseMap", FIELDS, KeyMapVisitor)
     }
 }
@@ -218,3 +294,49 @@ fn to_modifier(slice: &[String]) -> Result<Modifiers, String> {
     }
     Ok(modifiers)
 }
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::seat::pointer::AxisScroll;
+
+    use super::{Mouse, Scroll};
+
+    fn axis(absolute: f64, discrete: i32, stop: bool) -> AxisScroll {
+        AxisScroll {
+            absolute,
+            discrete,
+            stop,
+        }
+    }
+
+    #[test]
+    fn smooth_scroll() {
+        let mut scroll = Scroll::new(10.);
+        let none = AxisScroll::default();
+        assert!(scroll.axis(&none, &axis(6., 0, false)).is_empty());
+        assert_eq!(
+            scroll.axis(&none, &axis(16., 0, false)),
+            [Mouse::ScrollDown, Mouse::ScrollDown]
+        );
+        // 2 pixels left over, changing direction eats it first
+        assert!(scroll.axis(&none, &axis(-9., 0, false)).is_empty());
+        assert_eq!(
+            scroll.axis(&axis(-12., 0, false), &axis(-3., 0, false)),
+            [Mouse::ScrollLeft, Mouse::ScrollUp]
+        );
+        // stop throws away what's left
+        assert!(scroll.axis(&none, &axis(-9., 0, true)).is_empty());
+        assert!(scroll.axis(&none, &axis(-9., 0, false)).is_empty());
+    }
+
+    #[test]
+    fn discrete_scroll() {
+        let mut scroll = Scroll::new(10.);
+        let none = AxisScroll::default();
+        assert_eq!(
+            scroll.axis(&axis(30., 2, false), &none),
+            [Mouse::ScrollRight, Mouse::ScrollRight]
+        );
+        assert_eq!(scroll.axis(&none, &axis(-1., -1, false)), [Mouse::ScrollUp]);
+    }
+}
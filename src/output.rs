@@ -1,12 +1,16 @@
 use std::error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
 
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
+use smithay_client_toolkit::compositor::Region;
 use smithay_client_toolkit::shm::slot::Buffer;
 use smithay_client_toolkit::{
     output::OutputInfo,
-    shell::wlr_layer::{KeyboardInteractivity, LayerSurface},
+    shell::{
+        wlr_layer::{KeyboardInteractivity, LayerSurface},
+        WaylandSurface,
+    },
     shm::{slot::SlotPool, Shm},
 };
 use wayland_client::{
@@ -15,26 +19,53 @@ use wayland_client::{
     Connection,
 };
 
+use crate::background::Background;
+use crate::board::{Board, BoardOp, Boards};
 use crate::pause::{self, ScreenCopy};
+use crate::tools::registry;
+use crate::tools::snap::Guide;
 use crate::tools::Tool;
 
-pub fn restore(saved: &mut Vec<Saved>, info: &OutputInfo) -> Vec<Box<dyn Tool>> {
+/// Take the save of the output out of saved
+pub fn restore(saved: &mut Vec<Saved>, info: &OutputInfo) -> Option<Saved> {
     let index = saved
         .iter()
-        .position(|s| s.id == info.id && s.model == info.model && s.make == info.make);
-    if let Some(index) = index {
-        saved.remove(index).draws
-    } else {
-        Vec::new()
-    }
+        .position(|s| s.id == info.id && s.model == info.model && s.make == info.make)?;
+    Some(saved.remove(index))
 }
 
-// #[derive(Deserialize)]
+#[derive(Deserialize)]
 pub struct Saved {
-    id: u32, // We use these 3 values to compare outputs, so we know if we should load an input
-    model: String,
-    make: String,
-    draws: Vec<Box<dyn Tool>>,
+    pub id: u32, // We use these 3 values to compare outputs, so we know if we should load an input
+    pub model: String,
+    pub make: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// The drawings of the board that was shown
+    #[serde(deserialize_with = "registry::deserialize_draws")]
+    pub draws: Vec<Box<dyn Tool>>,
+    #[serde(default)]
+    pub board: usize,
+    #[serde(default)]
+    pub boards: Vec<Board>,
+    /// Older saves don't have one, the output keeps its own
+    #[serde(default)]
+    pub background: Option<Background>,
+}
+
+/// Read a save file, either a single output written by OutPut::save
+/// or a list of outputs written by Runtime::save_all
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Saved>, Box<dyn error::Error>> {
+    let str = fs::read_to_string(path)?;
+    if str.trim_start().starts_with('[') {
+        Ok(serde_json::from_str(&str)?)
+    } else {
+        Ok(vec![serde_json::from_str(&str)?])
+    }
 }
 
 pub struct OutPut {
@@ -47,7 +78,12 @@ pub struct OutPut {
     pub interactivity: KeyboardInteractivity,
     pub layer: LayerSurface,
     pub configured: bool,
+    /// The drawings of the board that is shown
     pub draws: Vec<Box<dyn Tool>>,
+    pub boards: Boards,
+    pub background: Background,
+    /// Where the shape being drawn snapped to, not saved
+    pub guides: Vec<Guide>,
     pub screencopy: Option<ScreenCopy>,
     pub fgcolor: raqote::SolidSource,
 }
@@ -58,11 +94,17 @@ impl Serialize for OutPut {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Saved", 4)?;
+        let mut state = serializer.serialize_struct("Saved", 10)?;
         state.serialize_field("id", &self.info.id)?;
         state.serialize_field("model", &self.info.model)?;
         state.serialize_field("make", &self.info.make)?;
+        state.serialize_field("name", &self.info.name)?;
+        state.serialize_field("width", &self.width)?;
+        state.serialize_field("height", &self.height)?;
         state.serialize_field("draws", &self.draws)?;
+        state.serialize_field("board", &self.boards.current())?;
+        state.serialize_field("boards", self.boards.list())?;
+        state.serialize_field("background", &self.background)?;
         state.end()
     }
 }
@@ -95,6 +137,9 @@ impl OutPut {
             buffers,
             configured: false,
             draws: Vec::new(),
+            boards: Boards::default(),
+            background: Background::default(),
+            guides: Vec::new(),
             screencopy: None,
             interactivity: KeyboardInteractivity::Exclusive,
             fgcolor,
@@ -137,6 +182,25 @@ impl OutPut {
         }
     }
 
+    /// Passing a region makes it the input region of the output, an
+    /// empty region lets all input pass through. None restores it.
+    pub fn set_passthrough(&mut self, region: Option<&Region>) {
+        let surface = self.layer.wl_surface();
+        match region {
+            Some(region) => {
+                self.layer
+                    .set_keyboard_interactivity(KeyboardInteractivity::None);
+                surface.set_input_region(Some(region.wl_region()));
+            }
+            None => {
+                self.layer
+                    .set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+                surface.set_input_region(None);
+            }
+        }
+        self.layer.commit();
+    }
+
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn error::Error>> {
         // pub fn save(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
         // Something like this
@@ -147,20 +211,37 @@ impl OutPut {
         Ok(())
     }
 
-    // TODO:
-    pub fn restore(&mut self, path: &str) -> Result<(), Box<dyn error::Error>> {
-        // let saved: Vec<Saved> = File::open(path)
-        //     .ok()
-        //     .map(|f| serde_json::from_reader(f).expect("Couldn't parse saved file"));
-        // let index = saved.iter().position(|s| {
-        //     s.id == self.info.id && s.model == self.info.model && s.make == self.info.make
-        // });
-        // // index.ok_or("No screen found");
-        // if let Some(index) = index {
-        //     self.draws = saved[index].draws;
-        // }
+    /// Replace the drawings with the ones saved for this output. The
+    /// drawings are kept if the file has no save for this output.
+    pub fn restore<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn error::Error>> {
+        let mut saved = load(path)?;
+        let saved = restore(&mut saved, &self.info).ok_or("There is no save for this output")?;
+        self.restore_saved(Some(saved));
         Ok(())
     }
+
+    /// Replace the boards with saved, or start over if there is no save,
+    /// like a new output does. The background is only replaced if one
+    /// was saved.
+    pub fn restore_saved(&mut self, saved: Option<Saved>) {
+        match saved {
+            Some(saved) => {
+                self.draws = saved.draws;
+                self.boards = Boards::from_saved(saved.boards, saved.board);
+                if let Some(background) = saved.background {
+                    self.background = background;
+                }
+            }
+            None => {
+                self.draws = Vec::new();
+                self.boards = Boards::default();
+            }
+        }
+    }
+
+    pub fn board(&mut self, op: BoardOp) -> Result<(), String> {
+        self.boards.apply(op, &mut self.draws)
+    }
     pub fn start_draw(&mut self, tool: Box<dyn Tool>) {
         self.draws.push(tool);
     }
@@ -199,7 +280,7 @@ impl Buffers {
         &self.buffers[self.current]
     }
 
-    pub fn canvas<'a>(&'a self, pool: &'a mut SlotPool) -> Option<&mut [u8]> {
+    pub fn canvas<'a>(&'a self, pool: &'a mut SlotPool) -> Option<&'a mut [u8]> {
         self.buffers[self.current].canvas(pool)
     }
 }
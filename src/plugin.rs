@@ -0,0 +1,547 @@
+// Native plugins, shared libraries in $XDG_DATA_HOME/sketchover/plugins
+// that add tools and hooks through the C ABI in the sketchover-plugin
+// crate. Plugin tools are added to the tool registry and drawn with
+// Scripted like the tools from lua, so their shapes are saved as paths and
+// can be restored without the plugin.
+
+use std::error;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use libloading::{Library, Symbol};
+use sketchover_plugin::{
+    Hooks, Host, Paths, Plugin, PluginEntry, Registrar, ToolDef, ABI_VERSION, ENTRY_SYMBOL,
+};
+use xdg::BaseDirectories;
+
+use crate::ipc::{Request, Response};
+use crate::mousemap::Mouse;
+use crate::runtime::{Events, Runtime};
+use crate::tools::draw::draw::Draw;
+use crate::tools::draw::scripted::{Paint, PathOp, Script, Scripted};
+use crate::tools::registry::ToolRegistry;
+
+/// The hooks of the plugins that have been loaded, their tools are in the
+/// tool registry
+#[derive(Default)]
+pub struct Plugins {
+    hooks: Vec<PluginHooks>,
+}
+
+struct PluginHooks {
+    hooks: Hooks,
+    // None for plugins that are part of the binary, like in the tests
+    _lib: Option<Rc<Library>>,
+}
+
+impl Drop for PluginHooks {
+    fn drop(&mut self) {
+        if let Some(free) = self.hooks.free {
+            unsafe { free(self.hooks.data) }
+        }
+    }
+}
+
+/// The directory plugins are loaded from
+pub fn plugin_dir() -> Result<PathBuf, Box<dyn error::Error>> {
+    let xdg_dirs = BaseDirectories::with_prefix("sketchover")?;
+    Ok(xdg_dirs.get_data_home().join("plugins"))
+}
+
+impl Plugins {
+    /// Load all the shared libraries in dir. Plugins that can't be loaded
+    /// are logged and skipped, a missing dir just means no plugins.
+    pub fn load_dir(&mut self, dir: &Path, tools: &mut ToolRegistry) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "so"))
+            .collect();
+        // the first plugin to add a tool name gets it
+        paths.sort();
+        for path in paths {
+            if let Err(e) = self.load(&path, tools) {
+                log::warn!("Couldn't load plugin {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Load a plugin. This runs code from the library, so only load
+    /// plugins you trust.
+    pub fn load(
+        &mut self,
+        path: &Path,
+        tools: &mut ToolRegistry,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let lib = unsafe { Library::new(path)? };
+        let plugin = unsafe {
+            let entry: Symbol<PluginEntry> = lib.get(ENTRY_SYMBOL)?;
+            entry()
+        };
+        let name = self.register(plugin, Some(Rc::new(lib)), tools)?;
+        log::info!("Loaded plugin {name} from {}", path.display());
+        Ok(())
+    }
+
+    /// Check the version of the plugin and let it register its tools and
+    /// hooks. Returns the name of the plugin.
+    fn register(
+        &mut self,
+        plugin: *const Plugin,
+        lib: Option<Rc<Library>>,
+        tools: &mut ToolRegistry,
+    ) -> Result<String, Box<dyn error::Error>> {
+        let plugin = unsafe { plugin.as_ref() }.ok_or("The plugin entry returned null")?;
+        if plugin.abi_version != ABI_VERSION {
+            return Err(format!(
+                "The plugin is built for abi version {}, sketchover uses {ABI_VERSION}",
+                plugin.abi_version
+            )
+            .into());
+        }
+        let name = unsafe { string(plugin.name) }.ok_or("The plugin has no name")?;
+
+        let mut registration = Registration {
+            plugins: self,
+            tools,
+            lib,
+        };
+        let mut registrar = Registrar {
+            ctx: &mut registration as *mut Registration as *mut c_void,
+            add_tool,
+            add_hooks,
+            log,
+        };
+        unsafe { (plugin.register)(&mut registrar) };
+        Ok(name)
+    }
+}
+
+unsafe fn string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+// The ctx of the registrar
+struct Registration<'a> {
+    plugins: &'a mut Plugins,
+    tools: &'a mut ToolRegistry,
+    lib: Option<Rc<Library>>,
+}
+
+unsafe extern "C" fn add_tool(ctx: *mut c_void, tool: *const ToolDef) -> bool {
+    let registration = &mut *(ctx as *mut Registration);
+    let Some(def) = tool.as_ref() else {
+        return false;
+    };
+    let Some(name) = string(def.name) else {
+        return false;
+    };
+    let (def, lib) = (*def, registration.lib.clone());
+    let tool = name.clone();
+    let create = Rc::new(move |pos: (f64, f64), draw: Draw| {
+        let script = PluginScript {
+            def,
+            state: unsafe { (def.start)(pos.0, pos.1) },
+            _lib: lib.clone(),
+        };
+        Box::new(Scripted::new(tool.clone(), draw, Box::new(script))) as _
+    });
+    let added = registration.tools.register(&name, create);
+    if !added {
+        log::warn!("A plugin tried to add {name}, but there already is such a tool");
+    }
+    added
+}
+
+unsafe extern "C" fn add_hooks(ctx: *mut c_void, hooks: *const Hooks) {
+    let registration = &mut *(ctx as *mut Registration);
+    if let Some(hooks) = hooks.as_ref() {
+        registration.plugins.hooks.push(PluginHooks {
+            hooks: *hooks,
+            _lib: registration.lib.clone(),
+        });
+    }
+}
+
+unsafe extern "C" fn log(level: u32, message: *const c_char) {
+    let level = match level {
+        0 | 1 => log::Level::Error,
+        2 => log::Level::Warn,
+        3 => log::Level::Info,
+        4 => log::Level::Debug,
+        _ => log::Level::Trace,
+    };
+    if let Some(message) = string(message) {
+        log::log!(level, "{message}");
+    }
+}
+
+/// Runs the update function of a plugin tool
+struct PluginScript {
+    def: ToolDef,
+    state: *mut c_void,
+    // the library must outlive the state, shapes can outlive the plugins
+    _lib: Option<Rc<Library>>,
+}
+
+impl Script for PluginScript {
+    fn update(&mut self, motion: (f64, f64)) -> Result<Vec<Paint>, Box<dyn error::Error>> {
+        let mut builder = PathBuilder::default();
+        let mut paths = Paths {
+            ctx: &mut builder as *mut PathBuilder as *mut c_void,
+            move_to,
+            line_to,
+            quad_to,
+            cubic_to,
+            arc,
+            close,
+            stroke,
+            fill,
+        };
+        unsafe { (self.def.update)(self.state, motion.0, motion.1, &mut paths) };
+        Ok(builder.paints)
+    }
+}
+
+impl Drop for PluginScript {
+    fn drop(&mut self) {
+        unsafe { (self.def.free)(self.state) }
+    }
+}
+
+// The ctx of Paths
+#[derive(Default)]
+struct PathBuilder {
+    ops: Vec<PathOp>,
+    paints: Vec<Paint>,
+}
+
+unsafe fn push(ctx: *mut c_void, op: PathOp) {
+    (*(ctx as *mut PathBuilder)).ops.push(op);
+}
+
+unsafe extern "C" fn move_to(ctx: *mut c_void, x: f32, y: f32) {
+    push(ctx, PathOp::MoveTo(x, y));
+}
+
+unsafe extern "C" fn line_to(ctx: *mut c_void, x: f32, y: f32) {
+    push(ctx, PathOp::LineTo(x, y));
+}
+
+unsafe extern "C" fn quad_to(ctx: *mut c_void, cx: f32, cy: f32, x: f32, y: f32) {
+    push(ctx, PathOp::QuadTo(cx, cy, x, y));
+}
+
+unsafe extern "C" fn cubic_to(
+    ctx: *mut c_void,
+    cx1: f32,
+    cy1: f32,
+    cx2: f32,
+    cy2: f32,
+    x: f32,
+    y: f32,
+) {
+    push(ctx, PathOp::CubicTo(cx1, cy1, cx2, cy2, x, y));
+}
+
+unsafe extern "C" fn arc(ctx: *mut c_void, x: f32, y: f32, r: f32, start: f32, sweep: f32) {
+    push(ctx, PathOp::Arc(x, y, r, start, sweep));
+}
+
+unsafe extern "C" fn close(ctx: *mut c_void) {
+    push(ctx, PathOp::Close);
+}
+
+unsafe extern "C" fn stroke(ctx: *mut c_void) {
+    let builder = &mut *(ctx as *mut PathBuilder);
+    let ops = std::mem::take(&mut builder.ops);
+    builder.paints.push(Paint::Stroke(ops));
+}
+
+unsafe extern "C" fn fill(ctx: *mut c_void) {
+    let builder = &mut *(ctx as *mut PathBuilder);
+    let ops = std::mem::take(&mut builder.ops);
+    builder.paints.push(Paint::Fill(ops));
+}
+
+// The ctx of the host passed to the hooks
+struct HostCtx<D> {
+    rt: *mut Runtime<D>,
+    response: CString,
+}
+
+unsafe extern "C" fn request<D: Events + 'static>(
+    ctx: *mut c_void,
+    request: *const c_char,
+) -> *const c_char {
+    let ctx = &mut *(ctx as *mut HostCtx<D>);
+    let request = string(request)
+        .ok_or_else(|| "The request is null".to_owned())
+        .and_then(|r| serde_json::from_str::<Request>(&r).map_err(|e| e.to_string()));
+    let response = match request {
+        Ok(request) => (*ctx.rt).handle_request(request),
+        Err(e) => Response::error(format!("Invalid request: {e}")),
+    };
+    // json escapes control characters, so there can't be a nul in it
+    let json = serde_json::to_string(&response).unwrap_or_default();
+    ctx.response = CString::new(json).unwrap_or_default();
+    ctx.response.as_ptr()
+}
+
+impl<D: Events + 'static> Runtime<D> {
+    /// Call the hooks of the plugins in the order they were loaded, until
+    /// one of them returns true. Returns if any of them did.
+    pub(crate) fn plugin_hooks(&mut self, mut call: impl FnMut(&Hooks, *mut Host) -> bool) -> bool {
+        if self.plugins().hooks.is_empty() {
+            return false;
+        }
+        // the hooks can't be borrowed while they change the runtime
+        let hooks: Vec<Hooks> = self.plugins().hooks.iter().map(|h| h.hooks).collect();
+        let mut ctx = HostCtx {
+            rt: self as *mut Runtime<D>,
+            response: CString::default(),
+        };
+        let mut host = Host {
+            ctx: &mut ctx as *mut HostCtx<D> as *mut c_void,
+            request: request::<D>,
+        };
+        hooks.iter().any(|hooks| call(hooks, &mut host))
+    }
+
+    /// Returns true if a plugin handled the key
+    pub(crate) fn plugin_key(&mut self, keysym: u32, press: bool) -> bool {
+        self.plugin_hooks(|hooks, host| match hooks.key {
+            Some(key) => unsafe { key(hooks.data, host, keysym, press) },
+            None => false,
+        })
+    }
+
+    /// Returns true if a plugin handled the button
+    pub(crate) fn plugin_mouse(&mut self, button: u32, press: bool, pos: (f64, f64)) -> bool {
+        self.plugin_hooks(|hooks, host| match hooks.mouse {
+            Some(mouse) => unsafe { mouse(hooks.data, host, button, press, pos.0, pos.1) },
+            None => false,
+        })
+    }
+
+    /// Returns true if a plugin handled the scroll step
+    pub(crate) fn plugin_scroll(&mut self, step: Mouse) -> bool {
+        let (dx, dy) = match step {
+            Mouse::ScrollUp => (0, -1),
+            Mouse::ScrollDown => (0, 1),
+            Mouse::ScrollLeft => (-1, 0),
+            Mouse::ScrollRight => (1, 0),
+            Mouse::Button(_) => return false,
+        };
+        self.plugin_hooks(|hooks, host| match hooks.scroll {
+            Some(scroll) => unsafe { scroll(hooks.data, host, dx, dy) },
+            None => false,
+        })
+    }
+
+    /// The response of the first plugin that answers a line sent to the
+    /// control socket, None if none of them did
+    pub(crate) fn plugin_request(&mut self, line: &[u8]) -> Option<serde_json::Value> {
+        let line = CString::new(line).ok()?;
+        let mut response = None;
+        self.plugin_hooks(|hooks, host| {
+            let Some(request) = hooks.request else {
+                return false;
+            };
+            let json = unsafe { string(request(hooks.data, host, line.as_ptr())) };
+            response = json.map(|json| {
+                serde_json::from_str(&json).unwrap_or_else(|e| {
+                    let error = Response::error(format!("A plugin sent an invalid response: {e}"));
+                    serde_json::to_value(error).unwrap_or_default()
+                })
+            });
+            response.is_some()
+        });
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, c_void, CStr};
+    use std::ptr;
+
+    use sketchover_plugin::{Hooks, Host, Paths, Plugin, Registrar, ToolDef, ABI_VERSION};
+    use smithay_client_toolkit::seat::keyboard::KeyEvent;
+
+    use super::Plugins;
+    use crate::mock_compositor::BTN_LEFT;
+    use crate::mousemap::Mouse;
+    use crate::runtime::{Events, Runtime};
+    use crate::tools::draw::draw::Draw;
+    use crate::tools::registry::ToolRegistry;
+    use crate::tools::Bounds;
+
+    struct Nothing;
+
+    impl Events for Nothing {
+        fn new_output(_r: &mut Runtime<Self>, _output: &mut crate::output::OutPut) {}
+        fn keybinding(_r: &mut Runtime<Self>, _event: KeyEvent, _press: bool) {}
+        fn mousebinding(_r: &mut Runtime<Self>, _event: u32, _press: bool) {}
+    }
+
+    // A tool that draws a line from where it started
+    unsafe extern "C" fn start(x: f64, y: f64) -> *mut c_void {
+        Box::into_raw(Box::new((x as f32, y as f32))) as *mut c_void
+    }
+
+    unsafe extern "C" fn update(state: *mut c_void, x: f64, y: f64, paths: *mut Paths) {
+        let (sx, sy) = *(state as *mut (f32, f32));
+        let paths = &*paths;
+        (paths.move_to)(paths.ctx, sx, sy);
+        (paths.line_to)(paths.ctx, x as f32, y as f32);
+        (paths.stroke)(paths.ctx);
+    }
+
+    unsafe extern "C" fn free(state: *mut c_void) {
+        drop(Box::from_raw(state as *mut (f32, f32)));
+    }
+
+    static LINE: ToolDef = ToolDef {
+        name: c"segment".as_ptr(),
+        start,
+        update,
+        free,
+    };
+
+    static PEN: ToolDef = ToolDef {
+        name: c"pen".as_ptr(),
+        start,
+        update,
+        free,
+    };
+
+    // eats the left button and scrolling sideways
+    unsafe extern "C" fn mouse(
+        _: *mut c_void,
+        _: *mut Host,
+        button: u32,
+        _: bool,
+        _: f64,
+        _: f64,
+    ) -> bool {
+        button == BTN_LEFT
+    }
+
+    unsafe extern "C" fn scroll(_: *mut c_void, _: *mut Host, dx: i32, _: i32) -> bool {
+        dx != 0
+    }
+
+    // answers ping, leaves the rest to sketchover
+    unsafe extern "C" fn request(
+        _: *mut c_void,
+        _: *mut Host,
+        request: *const c_char,
+    ) -> *const c_char {
+        match CStr::from_ptr(request).to_bytes() {
+            br#"{"command":"ping"}"# => c"{\"status\":\"pong\"}".as_ptr(),
+            br#"{"command":"bad"}"# => c"pong".as_ptr(),
+            _ => ptr::null(),
+        }
+    }
+
+    // eats x and asks for the outputs, the response ends up in data
+    unsafe extern "C" fn key(data: *mut c_void, host: *mut Host, keysym: u32, _: bool) -> bool {
+        let host = &*host;
+        let response = (host.request)(host.ctx, c"{\"command\":\"get_outputs\"}".as_ptr());
+        *(data as *mut String) = CStr::from_ptr(response).to_string_lossy().into_owned();
+        keysym == u32::from(b'x')
+    }
+
+    unsafe extern "C" fn register(registrar: *mut Registrar) {
+        let registrar = &*registrar;
+        assert!((registrar.add_tool)(registrar.ctx, &LINE));
+        assert!(!(registrar.add_tool)(registrar.ctx, &LINE));
+        assert!(!(registrar.add_tool)(registrar.ctx, &PEN));
+        let hooks = Hooks {
+            data: ptr::null_mut(),
+            new_output: None,
+            destroy_output: None,
+            key: Some(key),
+            mouse: Some(mouse),
+            scroll: Some(scroll),
+            request: Some(request),
+            shutdown: None,
+            free: None,
+        };
+        (registrar.add_hooks)(registrar.ctx, &hooks);
+    }
+
+    fn plugin(abi_version: u32) -> Plugin {
+        Plugin {
+            abi_version,
+            name: c"test".as_ptr(),
+            register,
+        }
+    }
+
+    #[test]
+    fn register_tools() {
+        let mut plugins = Plugins::default();
+        let mut tools = ToolRegistry::default();
+        let builtin = tools.names().count();
+        let err = plugins.register(&plugin(ABI_VERSION + 1), None, &mut tools);
+        assert!(err.is_err());
+        assert_eq!(tools.names().count(), builtin);
+
+        let name = plugins.register(&plugin(ABI_VERSION), None, &mut tools);
+        assert_eq!(name.unwrap(), "test");
+        assert_eq!(tools.names().last(), Some("segment"));
+        assert_eq!(tools.names().count(), builtin + 1);
+
+        let mut tool = tools.create("segment", (1., 2.), Draw::default()).unwrap();
+        tool.update((11., 22.));
+        assert_eq!(tool.name(), "segment");
+        assert_eq!(
+            tool.bounds(),
+            Some(Bounds {
+                x: 1.,
+                y: 2.,
+                width: 10.,
+                height: 20.
+            })
+        );
+    }
+
+    #[test]
+    fn hooks() {
+        let mut rt = Runtime::init(Nothing);
+        rt.plugins_mut()
+            .register(&plugin(ABI_VERSION), None, &mut ToolRegistry::default())
+            .unwrap();
+        // the hook puts the response in its data
+        let mut response = String::new();
+        rt.plugins_mut().hooks[0].hooks.data = &mut response as *mut String as *mut c_void;
+
+        let press = |rt: &mut Runtime<Nothing>, keysym: u32| {
+            rt.plugin_hooks(|hooks, host| unsafe {
+                (hooks.key.unwrap())(hooks.data, host, keysym, true)
+            })
+        };
+        assert!(!press(&mut rt, u32::from(b'y')));
+        assert_eq!(response, r#"{"status":"outputs","outputs":[]}"#);
+        assert!(press(&mut rt, u32::from(b'x')));
+
+        assert!(rt.plugin_mouse(BTN_LEFT, true, (1., 2.)));
+        assert!(!rt.plugin_mouse(BTN_LEFT + 1, true, (1., 2.)));
+        assert!(rt.plugin_scroll(Mouse::ScrollLeft));
+        assert!(!rt.plugin_scroll(Mouse::ScrollDown));
+
+        let pong = rt.plugin_request(br#"{"command":"ping"}"#);
+        assert_eq!(pong, Some(serde_json::json!({ "status": "pong" })));
+        let bad = rt.plugin_request(br#"{"command":"bad"}"#).unwrap();
+        assert_eq!(bad["status"], "error");
+        assert_eq!(rt.plugin_request(br#"{"command":"clear"}"#), None);
+    }
+}
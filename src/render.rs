@@ -0,0 +1,56 @@
+// Rendering of an output that doesn't care where the pixels end up,
+// a wayland buffer or a buffer in memory.
+
+use raqote::{DrawTarget, SolidSource};
+
+use crate::background::Background;
+use crate::tools::snap::{self, Guide};
+use crate::tools::Tool;
+
+pub struct Scene<'a> {
+    /// Cleared behind the drawings when there is no screenshot
+    pub fgcolor: SolidSource,
+    /// A screenshot of the output, used when the output is paused.
+    /// Needs to be in the same format and size as the target.
+    pub screenshot: Option<&'a [u8]>,
+    /// Drawn over the screenshot or foreground
+    pub background: Background,
+    pub draws: &'a [Box<dyn Tool>],
+    /// Drawn on top, while a shape snaps to others
+    pub guides: &'a [Guide],
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(fgcolor: SolidSource, draws: &'a [Box<dyn Tool>]) -> Self {
+        Scene {
+            fgcolor,
+            screenshot: None,
+            background: Background::default(),
+            draws,
+            guides: &[],
+        }
+    }
+
+    pub fn render(&self, dt: &mut DrawTarget<&mut [u32]>) {
+        // If we have paused the screen, we draw our screenshot
+        // on top. This gives the illusion that we have paused the screen.
+        match self.screenshot {
+            Some(screenshot) => {
+                let data = dt.get_data_u8_mut();
+                if data.len() == screenshot.len() {
+                    data.copy_from_slice(screenshot);
+                } else {
+                    log::error!("Screencopy doesn't match the size of the output");
+                    dt.clear(self.fgcolor);
+                }
+            }
+            None => dt.clear(self.fgcolor),
+        }
+        self.background.render(dt);
+
+        for draw in self.draws.iter() {
+            draw.draw(dt);
+        }
+        snap::draw_guides(self.guides, dt);
+    }
+}
@@ -1,10 +1,11 @@
 use std::error;
 use std::fs::File;
 use std::path::Path;
+use std::time::Duration;
 
 use calloop::{EventLoop, LoopSignal};
 use cursor_icon::CursorIcon;
-use smithay_client_toolkit::compositor::CompositorHandler;
+use smithay_client_toolkit::compositor::{CompositorHandler, Region};
 use smithay_client_toolkit::output::OutputHandler;
 use smithay_client_toolkit::reexports::protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
 use smithay_client_toolkit::registry::ProvidesRegistryState;
@@ -28,15 +29,23 @@ use smithay_client_toolkit::{
     delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
     delegate_registry, delegate_seat, delegate_shm, registry_handlers,
 };
-use wayland_client::protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_shm, wl_surface};
+use wayland_client::protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface};
 use wayland_client::{delegate_noop, QueueHandle, EventQueue};
 use wayland_client::{globals::registry_queue_init, Connection};
 
 use wayland_client::globals::GlobalList;
 use xkbcommon::xkb::keysyms;
 
-use crate::mousemap::{Mouse, MouseMap};
+use crate::board::BoardOp;
+use crate::ipc::{Request, Response};
+use crate::mousemap::{MouseMap, Scroll};
 use crate::output::OutPut;
+use crate::plugin::Plugins;
+use crate::render::Scene;
+use crate::tools::behaviour::ModifierMap;
+use crate::tools::draw::draw::Draw;
+use crate::tools::registry::ToolRegistry;
+use crate::tools::snap::Snap;
 use crate::tools::Tool;
 
 pub trait Events {
@@ -63,6 +72,30 @@ pub trait Events {
     fn mousebinding(r: &mut Runtime<Self>, event: u32, press: bool)
     where
         Self: Sized;
+
+    /// Called once for every step scrolled, with the modifiers held.
+    fn scrollbinding(_r: &mut Runtime<Self>, _scroll: MouseMap)
+    where
+        Self: Sized,
+    {
+    }
+
+    /// Ipc requests that the runtime can't handle by itself, like
+    /// changing the colour or the tool, are passed on here.
+    fn request(_r: &mut Runtime<Self>, request: &Request) -> Response
+    where
+        Self: Sized,
+    {
+        Response::error(format!("{request:?} is not supported"))
+    }
+
+    /// Called once when sketchover exits, however it exits. The outputs
+    /// are still there, so it's the place to save them.
+    fn shutdown(_r: &mut Runtime<Self>)
+    where
+        Self: Sized,
+    {
+    }
 }
 
 // The wayland state, not the sketchover runtime
@@ -92,14 +125,24 @@ pub struct Runtime<D> {
     outputs: Vec<OutPut>,
 
     drawing: bool,
+    passthrough: bool,
     last_pos: Option<(f64, f64)>,
     last_serial: Option<u32>,
 
     cursor_icon: CursorIcon,
 
     modifiers: Modifiers,
+    modifier_map: ModifierMap,
+    snap: Snap,
+    scroll: Scroll,
+
+    plugins: Plugins,
+    tools: ToolRegistry,
 }
 
+/// Pixels of smooth scrolling that makes one scroll step
+pub const DEFAULT_SCROLL_THRESHOLD: f64 = 10.;
+
 // impl<D: Bindable> Runtime<D> {
 impl<D: Events + 'static> Runtime<D> {
     pub fn current_output_id(&self) -> Option<u32> {
@@ -113,15 +156,47 @@ impl<D: Events + 'static> Runtime<D> {
             current_output: None,
             outputs: Vec::new(),
             drawing: false,
+            passthrough: false,
             last_pos: None,
             last_serial: None,
             modifiers: Modifiers::default(),
             cursor_icon: CursorIcon::Default,
+            modifier_map: ModifierMap::default(),
+            snap: Snap::default(),
+            scroll: Scroll::new(DEFAULT_SCROLL_THRESHOLD),
+            plugins: Plugins::default(),
+            tools: ToolRegistry::default(),
         }
     }
 
     pub fn run(&mut self, mut event_loop: EventLoop<Runtime<D>>) {
         let conn = Connection::connect_to_env().expect("Couldn't connect wayland compositor");
+        self.connect(conn, &event_loop);
+        self.run_until_exit(&mut event_loop);
+    }
+
+    /// Dispatch the connected event loop until exit is called, then
+    /// give D a chance to clean up with shutdown. What shutdown puts on
+    /// the event loop, like a save, is handled before returning.
+    pub fn run_until_exit(&mut self, event_loop: &mut EventLoop<Runtime<D>>) {
+        event_loop
+            .run(None, self, |_| {})
+            .expect("Eventloop failed");
+        self.plugin_hooks(|hooks, host| {
+            if let Some(shutdown) = hooks.shutdown {
+                unsafe { shutdown(hooks.data, host) }
+            }
+            false
+        });
+        D::shutdown(self);
+        event_loop
+            .dispatch(Some(Duration::ZERO), self)
+            .expect("Eventloop failed");
+    }
+
+    /// Bind the globals on conn and add the connection to the event loop.
+    /// Nothing happens until the event loop is dispatched.
+    pub fn connect(&mut self, conn: Connection, event_loop: &EventLoop<Runtime<D>>) {
         let (globals, event_queue): (GlobalList, EventQueue<Runtime<D>>) =
             registry_queue_init(&conn).expect("Couldn't create an event queue");
         let qh = event_queue.handle();
@@ -160,10 +235,6 @@ impl<D: Events + 'static> Runtime<D> {
         };
 
         self.wl_runtime = Some(wl_state);
-
-        event_loop
-            .run(None, self, |_| {})
-            .expect("Eventloop failed");
     }
 
     pub fn output(&self, surface: &wl_surface::WlSurface) -> Option<usize> {
@@ -172,10 +243,66 @@ impl<D: Events + 'static> Runtime<D> {
             .position(|o| o.layer.wl_surface() == surface)
     }
 
+    pub fn outputs(&self) -> &[OutPut] {
+        &self.outputs
+    }
+
+    pub fn outputs_mut(&mut self) -> &mut [OutPut] {
+        &mut self.outputs
+    }
+
     pub fn modifiers(&self) -> Modifiers {
         self.modifiers
     }
 
+    pub fn modifier_map(&self) -> &ModifierMap {
+        &self.modifier_map
+    }
+
+    /// Set which modifiers change the behaviour of the tool we draw with
+    pub fn set_modifier_map(&mut self, modifier_map: ModifierMap) {
+        self.modifier_map = modifier_map;
+    }
+
+    pub fn snap(&self) -> &Snap {
+        &self.snap
+    }
+
+    /// Set what the positions of the tools snap to, the pointer snaps
+    /// from its next motion
+    pub fn set_snap(&mut self, snap: Snap) {
+        self.snap = snap;
+        if !snap.enabled {
+            self.clear_guides();
+        }
+    }
+
+    pub fn plugins(&self) -> &Plugins {
+        &self.plugins
+    }
+
+    pub fn plugins_mut(&mut self) -> &mut Plugins {
+        &mut self.plugins
+    }
+
+    /// Load the plugins in dir, their tools are added to the registry
+    pub fn load_plugins(&mut self, dir: &Path) {
+        self.plugins.load_dir(dir, &mut self.tools);
+    }
+
+    pub fn tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    pub fn tools_mut(&mut self) -> &mut ToolRegistry {
+        &mut self.tools
+    }
+
+    /// How far to scroll on a touchpad before it counts as a step
+    pub fn set_scroll_threshold(&mut self, threshold: f64) {
+        self.scroll = Scroll::new(threshold);
+    }
+
     pub fn pos(&self) -> (f64, f64) {
         self.last_pos.unwrap_or((0.0, 0.0))
     }
@@ -183,24 +310,79 @@ impl<D: Events + 'static> Runtime<D> {
     /// Stop drawing
     pub fn stop_drawing(&mut self) {
         self.drawing = false;
+        self.clear_guides();
+    }
+
+    fn clear_guides(&mut self) {
+        for output in self.outputs.iter_mut() {
+            output.guides.clear();
+        }
     }
 
-    /// Start drawing using the specified tool
+    /// Snap the pointer at pos on the output and update the shape being
+    /// drawn. Returns where it snapped to.
+    fn motion(&mut self, idx: usize, pos: (f64, f64)) -> (f64, f64) {
+        let output = &mut self.outputs[idx];
+        let drawn = if self.drawing {
+            output.draws.split_last_mut()
+        } else {
+            None
+        };
+        let Some((last, shapes)) = drawn else {
+            // where the next shape starts, we don't know if that tool
+            // snaps to shapes so only the grid counts
+            return self.snap.snap(pos, &output.background, &[]).0;
+        };
+        let shapes: &[Box<dyn Tool>] = if last.snaps_to_shapes() { shapes } else { &[] };
+        let (pos, guides) = self.snap.snap(pos, &output.background, shapes);
+        output.guides = guides;
+        last.update(pos);
+        pos
+    }
+
+    /// Start drawing using the specified tool, created at pos
     /// We will draw until stop drawing is called.
-    pub fn start_drawing(&mut self, tool: Box<dyn Tool>) {
+    pub fn start_drawing(&mut self, mut tool: Box<dyn Tool>, pos: (f64, f64)) {
         if let Some(idx) = self.current_output {
             self.drawing = true;
+            tool.behaviour(&self.modifier_map.behaviour(&self.modifiers));
+            // the shape is there before the pointer moves
+            tool.update(pos);
             self.outputs[idx].start_draw(tool);
         }
     }
 
+    /// Remove the shape at index on the output idx, drawing stops when it
+    /// is the shape being drawn
+    pub fn remove_shape(&mut self, idx: usize, index: usize) -> Option<Box<dyn Tool>> {
+        let len = self.outputs[idx].draws.len();
+        if index >= len {
+            return None;
+        }
+        if self.drawing && self.current_output == Some(idx) && index == len - 1 {
+            self.stop_drawing();
+        }
+        Some(self.outputs[idx].draws.remove(index))
+    }
+
+    /// The style of the shape we are drawing right now, changes to it
+    /// show up in the shape. None when we aren't drawing.
+    pub fn active_draw(&mut self) -> Option<&mut Draw> {
+        if !self.drawing {
+            return None;
+        }
+        let idx = self.current_output?;
+        self.outputs[idx]
+            .draws
+            .last_mut()
+            .map(|tool| tool.style_mut())
+    }
+
     /// Save all outputs to path
     /// To be able to resume later on
     pub fn save_all<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn error::Error>> {
         let file = File::create(path)?;
-        for output in self.outputs.iter() {
-            serde_json::to_writer(&file, output)?;
-        }
+        serde_json::to_writer(file, &self.outputs)?;
         Ok(())
     }
 
@@ -230,6 +412,14 @@ impl<D: Events + 'static> Runtime<D> {
             output.draws.pop();
         }
     }
+
+    /// Change the boards of an output. A shape that is being drawn is
+    /// finished first, so it stays on its board.
+    pub fn board(&mut self, idx: usize, op: BoardOp) -> Result<(), String> {
+        self.stop_drawing();
+        self.outputs[idx].board(op)
+    }
+
     pub fn locate_output(&mut self, id: Option<u32>) -> Option<&mut OutPut> {
         if let Some(id) = id {
             for output in self.outputs.iter_mut() {
@@ -239,7 +429,7 @@ impl<D: Events + 'static> Runtime<D> {
             }
             None
         } else {
-            Some(&mut self.outputs[self.current_output.unwrap()])
+            self.current_output.map(|idx| &mut self.outputs[idx])
         }
     }
     pub fn locate_output_idx(&mut self, id: Option<u32>) -> Option<usize> {
@@ -254,12 +444,43 @@ impl<D: Events + 'static> Runtime<D> {
         self.current_output
     }
 
-    // TODO: Remove
-    // pub fn set_passthrough(&mut self, enable: bool) {
-    //     for output in self.outputs.iter_mut() {
-    //         output.set_enable(enable);
-    //     }
-    // }
+    pub fn passthrough(&self) -> bool {
+        self.passthrough
+    }
+
+    /// When passthrough is enabled, all input goes to the windows
+    /// below and drawing is disabled.
+    pub fn set_passthrough(&mut self, enable: bool) {
+        if self.wl_runtime.is_none() {
+            return;
+        }
+        for id in 0..self.outputs.len() {
+            self.set_output_passthrough(enable, id);
+        }
+        self.stop_drawing();
+        self.passthrough = enable;
+    }
+
+    /// Passthrough for one output, we keep drawing on the others
+    pub fn set_output_passthrough(&mut self, enable: bool, id: usize) {
+        let Some(ref rt) = self.wl_runtime else {
+            return;
+        };
+        let region = if enable {
+            match Region::new(&rt.compositor_state) {
+                Ok(region) => Some(region),
+                Err(e) => {
+                    log::error!("Couldn't create an input region: {e}");
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(output) = self.outputs.get_mut(id) {
+            output.set_passthrough(region.as_ref());
+        }
+    }
 
     pub fn set_pause(&mut self, pause: bool, id: usize) {
         let output = self.outputs.get_mut(id).expect("Can't get screen");
@@ -292,14 +513,15 @@ impl<D: Events + 'static> Runtime<D> {
                     .expect("Couldn't create canvas for drawing"),
             );
 
-            // If we have paused the screen, we draw our screenshot
-            // on top. This gives the illusion that we have paused the screen.
+            let mut scene = Scene::new(output.fgcolor, &output.draws);
+            scene.background = output.background;
+            scene.guides = &output.guides;
             if let Some(ref mut screen_copy) = output.screencopy {
                 let screen_canvas = screen_copy
                     .image
                     .canvas(&mut screen_copy.slot)
                     .expect("Couldn't copy the screencopy to the canvas");
-                canvas.clone_from_slice(screen_canvas);
+                scene.screenshot = Some(screen_canvas);
             }
 
             let mut dt = raqote::DrawTarget::from_backing(
@@ -307,14 +529,7 @@ impl<D: Events + 'static> Runtime<D> {
                 height as i32,
                 bytemuck::cast_slice_mut(canvas),
             );
-
-            if output.screencopy.is_none() {
-                dt.clear(output.fgcolor);
-            }
-
-            for draw in output.draws.iter() {
-                draw.draw(&mut dt);
-            }
+            scene.render(&mut dt);
 
             // Damage the entire window
             output
@@ -432,17 +647,28 @@ impl<D: Events + 'static> OutputHandler for Runtime<D> {
         let mut output = OutPut::new(output, width, height, info, pool, layer);
 
         D::new_output(self, &mut output);
+        let id = output.info.id;
         self.outputs.push(output);
+        self.plugin_hooks(|hooks, host| {
+            if let Some(new_output) = hooks.new_output {
+                unsafe { new_output(hooks.data, host, id) }
+            }
+            false
+        });
     }
 
     fn update_output(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
-        // if let Some(output) = self.outputs.iter().find(|o| o.output == output) {
-        // }
+        let Some(info) = runtime!(self).output_state.info(&output) else {
+            return;
+        };
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.output == output) {
+            output.info = info;
+        }
     }
 
     fn output_destroyed(
@@ -452,7 +678,14 @@ impl<D: Events + 'static> OutputHandler for Runtime<D> {
         output: wl_output::WlOutput,
     ) {
         if let Some(index) = self.outputs.iter().position(|o| o.output == output) {
-            D::destroy_output(self, self.outputs[index].info.id);
+            let id = self.outputs[index].info.id;
+            self.plugin_hooks(|hooks, host| {
+                if let Some(destroy_output) = hooks.destroy_output {
+                    unsafe { destroy_output(hooks.data, host, id) }
+                }
+                false
+            });
+            D::destroy_output(self, id);
             self.outputs.remove(index);
             if self.current_output.map(|i| i == index).unwrap_or(false) {
                 self.current_output = None;
@@ -579,6 +812,9 @@ impl<D: Events + 'static> KeyboardHandler for Runtime<D> {
             self.exit();
             return;
         }
+        if self.plugin_key(event.keysym.raw(), true) {
+            return;
+        }
         D::keybinding(self, event, true);
     }
 
@@ -590,6 +826,9 @@ impl<D: Events + 'static> KeyboardHandler for Runtime<D> {
         _: u32,
         event: KeyEvent,
     ) {
+        if self.plugin_key(event.keysym.raw(), false) {
+            return;
+        }
         D::keybinding(self, event, false);
     }
 
@@ -601,14 +840,20 @@ impl<D: Events + 'static> KeyboardHandler for Runtime<D> {
         _serial: u32,
         modifiers: Modifiers,
     ) {
+        let before = self.modifier_map.behaviour(&self.modifiers);
         self.modifiers = modifiers;
-        if let Some(idx) = self.current_output {
-            let output = &mut self.outputs[idx];
-            if self.drawing {
-                if let Some(_last) = output.draws.last_mut() {
-                    // TODO: Add modifier
-                    // last.update(None, &self.modifiers);
-                }
+        let behaviour = self.modifier_map.behaviour(&modifiers);
+        if !self.drawing || behaviour == before {
+            return;
+        }
+        let pos = self.pos();
+        if let Some(last) = self
+            .current_output
+            .and_then(|idx| self.outputs[idx].draws.last_mut())
+        {
+            // redo the last update, so the change shows without moving
+            if last.behaviour(&behaviour) {
+                last.update(pos);
             }
         }
     }
@@ -631,7 +876,7 @@ impl<D: Events + 'static> PointerHandler for Runtime<D> {
         &mut self,
         conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _pointer: &wl_pointer::WlPointer,
+        pointer: &wl_pointer::WlPointer,
         events: &[PointerEvent],
     ) {
         use PointerEventKind::*;
@@ -646,61 +891,42 @@ impl<D: Events + 'static> PointerHandler for Runtime<D> {
                         let _ = themed_pointer.set_cursor(conn, self.cursor_icon);
                     }
                     self.current_output = Some(output);
-                    self.last_pos = Some(event.position);
+                    let entered = &self.outputs[output];
+                    let (pos, _) = self.snap.snap(event.position, &entered.background, &[]);
+                    self.last_pos = Some(pos);
                 }
                 Leave { .. } => {}
                 Motion { .. } => {
-                    if let Some(output) = self
-                        .outputs
-                        .iter_mut()
-                        .find(|x| x.layer.wl_surface() == &event.surface)
-                    {
-                        self.last_pos = Some(event.position);
-                        if self.drawing {
-                            if let Some(last) = output.draws.last_mut() {
-                                last.update(event.position)
-                            } else {
-                                println!("This is empty!")
-                            }
-                        }
-                    }
+                    self.last_pos = Some(self.motion(output, event.position));
                 }
                 Press { button, serial, .. } => {
-                    if self.last_serial.map_or(true, |s| s != serial) {
+                    if self.last_serial != Some(serial)
+                        && !self.plugin_mouse(button, true, event.position)
+                    {
                         D::mousebinding(self, button, true);
                     }
                     self.last_serial = Some(serial);
                 }
                 Release { button, .. } => {
-                    D::mousebinding(self, button, false);
+                    if !self.plugin_mouse(button, false, event.position) {
+                        D::mousebinding(self, button, false);
+                    }
                 }
                 Axis {
-                    horizontal,
-                    vertical,
+                    ref horizontal,
+                    ref vertical,
                     ..
                 } => {
-                    // TODO: handle descrete and contineous differetly
-
-                    // descrete scrolling should should look for a treshold and
-                    // then just wait for a stop.
-                    let action = if vertical.absolute > 1. {
-                        Mouse::ScrollDown
-                    } else if vertical.absolute < -1. {
-                        Mouse::ScrollUp
-                    } else if horizontal.absolute > 1. {
-                        Mouse::ScrollLeft
-                    } else if horizontal.absolute < -1. {
-                        Mouse::ScrollRight
-                    } else {
-                        return;
-                    };
-                    // TODO: Handle these
-                    let mouse_map = MouseMap {
-                        event: action,
-                        modifier: self.modifiers,
-                    };
-                    // let cmd = self.mouse_map.get(&mouse_map).unwrap_or(&Command::Nop);
-                    // self.command(&cmd.clone());
+                    // Both of our pointers get the same axis events
+                    // and there is no serial to tell them apart.
+                    if runtime!(self).pointer.as_ref() != Some(pointer) {
+                        continue;
+                    }
+                    for step in self.scroll.axis(horizontal, vertical) {
+                        if !self.plugin_scroll(step) {
+                            D::scrollbinding(self, MouseMap::new(step, self.modifiers));
+                        }
+                    }
                 }
             }
         }
@@ -733,3 +959,346 @@ impl<D: Events + 'static> ProvidesRegistryState for Runtime<D> {
     }
     registry_handlers![OutputState, SeatState];
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use calloop::EventLoop;
+    use smithay_client_toolkit::seat::keyboard::KeyEvent;
+    use xkbcommon::xkb;
+
+    use super::{Events, Runtime};
+    use crate::mock_compositor::{MockCompositor, BTN_LEFT, BTN_RIGHT, KEY_ESC, KEY_U, SHIFT};
+    use crate::mousemap::MouseMap;
+    use crate::output::OutPut;
+    use crate::tools::draw::line::Line;
+    use crate::tools::draw::pen::Pen;
+    use crate::tools::snap::Snap;
+    use crate::tools::Tool;
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Vec<String>,
+    }
+
+    impl Events for Recorder {
+        fn new_output(r: &mut Runtime<Self>, output: &mut OutPut) {
+            let name = output.info.name.clone().unwrap_or_default();
+            r.data.log.push(format!("new_output {name}"));
+        }
+
+        fn destroy_output(r: &mut Runtime<Self>, _output_id: u32) {
+            r.data.log.push("destroy_output".to_owned());
+        }
+
+        fn keybinding(r: &mut Runtime<Self>, event: KeyEvent, press: bool) {
+            let key = xkb::keysym_get_name(event.keysym);
+            r.data.log.push(format!("key {key} {press}"));
+            if key == "u" && press {
+                r.undo();
+            }
+        }
+
+        fn mousebinding(r: &mut Runtime<Self>, button: u32, press: bool) {
+            if press {
+                let tool: Box<dyn Tool> = match button {
+                    BTN_RIGHT => Box::new(Pen::new(r.pos(), Default::default())),
+                    _ => Box::new(Line::new(r.pos(), Default::default())),
+                };
+                r.start_drawing(tool, r.pos());
+            } else {
+                r.stop_drawing();
+            }
+            r.data.log.push(format!("mouse {button} {press}"));
+        }
+
+        fn scrollbinding(r: &mut Runtime<Self>, scroll: MouseMap) {
+            r.data.log.push(format!("scroll {:?}", scroll.event));
+            if let Some(draw) = r.active_draw() {
+                draw.style.width += 1.;
+            }
+        }
+
+        fn shutdown(r: &mut Runtime<Self>) {
+            let outputs = r.outputs().len();
+            r.data.log.push(format!("shutdown {outputs}"));
+        }
+    }
+
+    struct Harness {
+        compositor: MockCompositor,
+        event_loop: EventLoop<'static, Runtime<Recorder>>,
+        rt: Runtime<Recorder>,
+    }
+
+    impl Harness {
+        /// Start a compositor with one configured output, DP-1
+        fn new() -> Self {
+            let (compositor, conn) = MockCompositor::new();
+            compositor.add_output("DP-1", 200, 100);
+            let event_loop = EventLoop::try_new().unwrap();
+            let mut rt = Runtime::init(Recorder::default());
+            rt.connect(conn, &event_loop);
+            let mut harness = Harness {
+                compositor,
+                event_loop,
+                rt,
+            };
+            harness.dispatch_until("DP-1 configured", |h| {
+                h.rt.outputs().iter().any(|o| o.configured)
+            });
+            harness
+        }
+
+        fn dispatch_until<F>(&mut self, what: &str, done: F)
+        where
+            F: Fn(&Harness) -> bool,
+        {
+            let start = Instant::now();
+            while !done(self) {
+                assert!(
+                    start.elapsed() < Duration::from_secs(5),
+                    "Timed out waiting for {what}"
+                );
+                self.event_loop
+                    .dispatch(Some(Duration::from_millis(5)), &mut self.rt)
+                    .unwrap();
+            }
+        }
+
+        fn logged(&self, entry: &str) -> bool {
+            self.rt.data.log.iter().any(|e| e == entry)
+        }
+
+        fn count(&self, entry: &str) -> usize {
+            self.rt.data.log.iter().filter(|e| *e == entry).count()
+        }
+    }
+
+    #[test]
+    fn new_output() {
+        let h = Harness::new();
+        let output = &h.rt.outputs()[0];
+        assert_eq!((output.width, output.height), (200, 100));
+        assert!(h.logged("new_output DP-1"));
+    }
+
+    #[test]
+    fn draw_with_pointer() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.motion((50., 60.));
+        h.compositor.button(BTN_LEFT, false);
+        h.compositor.motion((80., 80.));
+        h.dispatch_until("button release", |h| {
+            h.logged(&format!("mouse {BTN_LEFT} false"))
+        });
+
+        let draws = &h.rt.outputs()[0].draws;
+        assert_eq!(draws.len(), 1);
+        let line = serde_json::to_value(&draws[0]).unwrap();
+        assert_eq!(line["start"], serde_json::json!([10., 10.]));
+        assert_eq!(line["stop"], serde_json::json!([50., 60.]));
+    }
+
+    #[test]
+    fn keybinding() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.button(BTN_LEFT, false);
+        h.compositor.key(KEY_U, true);
+        h.compositor.key(KEY_U, false);
+        h.dispatch_until("key release", |h| h.logged("key u false"));
+
+        assert!(h.logged("key u true"));
+        assert!(h.rt.outputs()[0].draws.is_empty());
+    }
+
+    #[test]
+    fn shutdown() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.key(KEY_ESC, true);
+        h.rt.run_until_exit(&mut h.event_loop);
+        assert_eq!(h.count("shutdown 1"), 1);
+    }
+
+    #[test]
+    fn pause() {
+        let mut h = Harness::new();
+        h.rt.set_pause(true, 0);
+        assert!(h.rt.outputs()[0].screencopy.is_some());
+        h.rt.set_pause(false, 0);
+        assert!(h.rt.outputs()[0].screencopy.is_none());
+    }
+
+    #[test]
+    fn output_removed() {
+        let mut h = Harness::new();
+        h.compositor.add_output("HDMI-A-1", 100, 100);
+        h.dispatch_until("second output", |h| h.rt.outputs().len() == 2);
+        h.compositor.remove_output("DP-1");
+        h.dispatch_until("output removed", |h| h.rt.outputs().len() == 1);
+
+        assert!(h.logged("destroy_output"));
+        assert_eq!(h.rt.outputs()[0].info.name.as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut h = Harness::new();
+        h.rt.set_passthrough(true);
+        h.dispatch_until("empty input region", |h| {
+            h.compositor.layers()[0].input_region == Some(Vec::new())
+        });
+        assert_eq!(h.compositor.layers()[0].keyboard_interactivity, 0);
+
+        h.rt.set_passthrough(false);
+        h.dispatch_until("input region reset", |h| {
+            h.compositor.layers()[0].input_region.is_none()
+        });
+        assert_eq!(h.compositor.layers()[0].keyboard_interactivity, 1);
+    }
+
+    #[test]
+    fn output_passthrough() {
+        let mut h = Harness::new();
+        h.compositor.add_output("HDMI-A-1", 100, 100);
+        h.dispatch_until("second output", |h| {
+            h.rt.outputs().len() == 2 && h.rt.outputs().iter().all(|o| o.configured)
+        });
+        h.rt.set_output_passthrough(true, 1);
+        h.dispatch_until("empty input region", |h| {
+            h.compositor.layers()[1].input_region == Some(Vec::new())
+        });
+        assert_eq!(h.compositor.layers()[0].input_region, None);
+        assert!(!h.rt.passthrough());
+    }
+
+    #[test]
+    fn modifiers_while_drawing() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.motion((50., 20.));
+        h.compositor.modifiers(SHIFT);
+        h.dispatch_until("shift", |h| h.rt.modifiers().shift);
+        let line = serde_json::to_value(&h.rt.outputs()[0].draws[0]).unwrap();
+        assert_eq!(line["stop"], serde_json::json!([50., 10.]));
+
+        h.compositor.modifiers(0);
+        h.dispatch_until("shift released", |h| !h.rt.modifiers().shift);
+        let line = serde_json::to_value(&h.rt.outputs()[0].draws[0]).unwrap();
+        assert_eq!(line["stop"], serde_json::json!([50., 20.]));
+        h.compositor.button(BTN_LEFT, false);
+
+        // the pen doesn't get a point for every modifier change
+        h.compositor.button(BTN_RIGHT, true);
+        h.compositor.motion((60., 30.));
+        h.dispatch_until("pen", |h| h.rt.pos() == (60., 30.));
+        let points = |h: &Harness| {
+            let pen = serde_json::to_value(&h.rt.outputs()[0].draws[1]).unwrap();
+            pen["lines"].as_array().unwrap().len()
+        };
+        let before = points(&h);
+        h.compositor.modifiers(SHIFT);
+        h.dispatch_until("shift", |h| h.rt.modifiers().shift);
+        h.compositor.modifiers(0);
+        h.dispatch_until("shift released", |h| !h.rt.modifiers().shift);
+        assert_eq!(points(&h), before);
+    }
+
+    #[test]
+    fn snap_while_drawing() {
+        let mut h = Harness::new();
+        h.rt.set_snap(Snap {
+            enabled: true,
+            ..Default::default()
+        });
+        h.compositor.enter("DP-1", (30., 34.));
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.motion((70., 60.));
+        h.dispatch_until("motion", |h| h.rt.pos() == (64., 64.));
+        let line = serde_json::to_value(&h.rt.outputs()[0].draws[0]).unwrap();
+        assert_eq!(line["start"], serde_json::json!([32., 32.]));
+        assert_eq!(line["stop"], serde_json::json!([64., 64.]));
+
+        // the end of the second line lines up with the middle of the first
+        h.compositor.button(BTN_LEFT, false);
+        h.compositor.motion((100., 100.));
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.motion((130., 70.));
+        h.dispatch_until("second line", |h| h.rt.outputs()[0].draws.len() == 2);
+        h.dispatch_until("motion", |h| h.rt.pos() == (128., 64.));
+        assert_eq!(h.rt.outputs()[0].guides.len(), 1);
+        h.compositor.button(BTN_LEFT, false);
+        h.dispatch_until("release", |h| h.rt.outputs()[0].guides.is_empty());
+
+        // not drawing, the middle of the first line is close but only the
+        // grid counts
+        h.compositor.motion((45., 49.));
+        h.dispatch_until("grid", |h| h.rt.pos() == (32., 64.));
+    }
+
+    #[test]
+    fn scroll() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.wheel(2);
+        h.compositor.wheel(-1);
+        // 12 pixels, one step and 2 left over that stop throws away
+        h.compositor.smooth_scroll(4.);
+        h.compositor.smooth_scroll(8.);
+        h.compositor.scroll_stop();
+        h.compositor.smooth_scroll(9.);
+        h.compositor.button(BTN_LEFT, true);
+        h.dispatch_until("button press", |h| {
+            h.logged(&format!("mouse {BTN_LEFT} true"))
+        });
+
+        assert_eq!(h.count("scroll ScrollDown"), 3);
+        assert_eq!(h.count("scroll ScrollUp"), 1);
+    }
+
+    #[test]
+    fn shape_before_motion() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.button(BTN_LEFT, true);
+        h.dispatch_until("press", |h| h.rt.outputs()[0].draws.len() == 1);
+        let line = serde_json::to_value(&h.rt.outputs()[0].draws[0]).unwrap();
+        assert_eq!(line["stop"], serde_json::json!([10., 10.]));
+    }
+
+    #[test]
+    fn remove_while_drawing() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.button(BTN_LEFT, true);
+        h.dispatch_until("press", |h| h.rt.outputs()[0].draws.len() == 1);
+        assert!(h.rt.remove_shape(0, 1).is_none());
+        assert!(h.rt.remove_shape(0, 0).is_some());
+        assert!(h.rt.active_draw().is_none());
+        h.compositor.motion((40., 40.));
+        h.dispatch_until("motion", |h| h.rt.pos() == (40., 40.));
+        assert!(h.rt.outputs()[0].draws.is_empty());
+    }
+
+    #[test]
+    fn style_while_drawing() {
+        let mut h = Harness::new();
+        h.compositor.enter("DP-1", (10., 10.));
+        h.compositor.wheel(1);
+        h.compositor.button(BTN_LEFT, true);
+        h.compositor.wheel(2);
+        h.compositor.button(BTN_LEFT, false);
+        h.compositor.wheel(1);
+        h.dispatch_until("scrolls", |h| h.count("scroll ScrollDown") == 4);
+
+        let line = serde_json::to_value(&h.rt.outputs()[0].draws[0]).unwrap();
+        assert_eq!(line["draw"]["style"]["width"], serde_json::json!(3.));
+    }
+}
@@ -0,0 +1,122 @@
+// Modifiers held while drawing change how a tool behaves. Which
+// modifier does what is configured with a ModifierMap, the tools
+// only see the resulting Behaviour.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::seat::keyboard::Modifiers;
+
+/// What a tool should do differently, tools ignore the parts that
+/// doesn't make sense for them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Behaviour {
+    /// Lock lines to horizontal or vertical
+    pub straighten: bool,
+    /// Keep the width and the height the same
+    pub square: bool,
+    /// The start position is the centre of the shape
+    pub from_centre: bool,
+    /// Snap the angle of lines to steps of SNAP_ANGLE
+    pub snap: bool,
+}
+
+/// Angle in degrees that lines snap to
+pub const SNAP_ANGLE: f64 = 15.;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Logo,
+}
+
+impl Modifier {
+    pub fn held(&self, modifiers: &Modifiers) -> bool {
+        match self {
+            Modifier::Ctrl => modifiers.ctrl,
+            Modifier::Alt => modifiers.alt,
+            Modifier::Shift => modifiers.shift,
+            Modifier::Logo => modifiers.logo,
+        }
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ctrl" => Ok(Modifier::Ctrl),
+            "alt" => Ok(Modifier::Alt),
+            "shift" => Ok(Modifier::Shift),
+            "logo" => Ok(Modifier::Logo),
+            x => Err(format!("{x} is not a modifier")),
+        }
+    }
+}
+
+/// The modifier that turns on each behaviour, None disables it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ModifierMap {
+    pub straighten: Option<Modifier>,
+    pub square: Option<Modifier>,
+    pub from_centre: Option<Modifier>,
+    pub snap: Option<Modifier>,
+}
+
+impl Default for ModifierMap {
+    fn default() -> Self {
+        ModifierMap {
+            straighten: Some(Modifier::Shift),
+            square: Some(Modifier::Shift),
+            from_centre: Some(Modifier::Ctrl),
+            snap: Some(Modifier::Alt),
+        }
+    }
+}
+
+impl ModifierMap {
+    pub fn behaviour(&self, modifiers: &Modifiers) -> Behaviour {
+        let held = |m: Option<Modifier>| m.is_some_and(|m| m.held(modifiers));
+        Behaviour {
+            straighten: held(self.straighten),
+            square: held(self.square),
+            from_centre: held(self.from_centre),
+            snap: held(self.snap),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::seat::keyboard::Modifiers;
+
+    use super::{Behaviour, Modifier, ModifierMap};
+
+    #[test]
+    fn map_modifiers() {
+        let map = ModifierMap {
+            snap: None,
+            from_centre: Some(Modifier::Logo),
+            ..Default::default()
+        };
+        let modifiers = Modifiers {
+            shift: true,
+            alt: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            map.behaviour(&modifiers),
+            Behaviour {
+                straighten: true,
+                square: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(map.behaviour(&Modifiers::default()), Behaviour::default());
+    }
+}
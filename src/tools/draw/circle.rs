@@ -1,21 +1,57 @@
+use std::time::Instant;
+
 use raqote::DrawTarget;
 
-use crate::tools::draw::draw;
-use crate::tools::draw::draw::Draw;
-use crate::tools::Tool;
-use serde::Serialize;
+use crate::tools::behaviour::Behaviour;
+use crate::tools::draw::draw::{self, Draw};
+use crate::tools::{Bounds, Tool};
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Circle {
     draw: Draw,
     start: (f64, f64),
     stop: (f64, f64),
     // elipse: bool,
+    // where we started, start moves when drawing from the centre
+    #[serde(skip)]
+    anchor: (f64, f64),
+    #[serde(skip)]
+    behaviour: Behaviour,
+    // shapes read from a save count from when they were read
+    #[serde(skip, default = "Instant::now")]
+    created: Instant,
 }
 
+impl Circle {
+    pub fn new(pos: (f64, f64), draw: Draw) -> Self {
+        Circle {
+            draw,
+            start: pos,
+            stop: pos,
+            anchor: pos,
+            behaviour: Behaviour::default(),
+            created: Instant::now(),
+        }
+    }
+}
+
+#[typetag::serialize(name = "circle")]
 impl Tool for Circle {
+    // start and stop are the two ends of the diameter
     fn update(&mut self, motion: (f64, f64)) {
-        self.stop = draw::diff(self.start, motion);
+        self.stop = motion;
+        self.start = if self.behaviour.from_centre {
+            let (dx, dy) = draw::diff(self.anchor, motion);
+            (self.anchor.0 - dx, self.anchor.1 - dy)
+        } else {
+            self.anchor
+        };
+    }
+
+    fn behaviour(&mut self, behaviour: &Behaviour) -> bool {
+        self.behaviour = *behaviour;
+        true
     }
 
     fn draw(&self, dt: &mut DrawTarget<&mut [u32]>) {
@@ -40,4 +76,36 @@ impl Tool for Circle {
             &raqote::DrawOptions::new(),
         );
     }
+
+    fn style(&self) -> &Draw {
+        &self.draw
+    }
+
+    fn style_mut(&mut self) -> &mut Draw {
+        &mut self.draw
+    }
+
+    fn name(&self) -> &str {
+        "circle"
+    }
+
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        let centre = (
+            (self.start.0 + self.stop.0) / 2.,
+            (self.start.1 + self.stop.1) / 2.,
+        );
+        let (dx, dy) = draw::diff(self.start, self.stop);
+        let r = dx.hypot(dy) / 2.;
+        Bounds::around([(centre.0 - r, centre.1 - r), (centre.0 + r, centre.1 + r)])
+    }
+
+    fn translate(&mut self, offset: (f64, f64)) {
+        self.start = draw::translate(self.start, offset);
+        self.stop = draw::translate(self.stop, offset);
+        self.anchor = draw::translate(self.anchor, offset);
+    }
 }
@@ -36,7 +36,7 @@ impl Serialize for Draw {
     }
 }
 
-fn parse_solid(str: &str) -> Result<SolidSource, ParseHexColorError> {
+pub fn parse_solid(str: &str) -> Result<SolidSource, ParseHexColorError> {
     let hex = HexColor::parse(str)?;
     Ok(SolidSource {
         r: hex.r,
@@ -117,7 +117,7 @@ impl<'de> Deserialize<'de> for Draw {
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["style", "color"];
+        const FIELDS: &[&str] = &["style", "color"];
         deserializer.deserialize_struct("Draw", FIELDS, DrawVisitor)
     }
 }
@@ -152,6 +152,10 @@ pub fn diff(pos: (f64, f64), motion: (f64, f64)) -> (f64, f64) {
     (motion.0 - pos.0, motion.1 - pos.1)
 }
 
+pub fn translate(pos: (f64, f64), offset: (f64, f64)) -> (f64, f64) {
+    (pos.0 + offset.0, pos.1 + offset.1)
+}
+
 impl Serialize for StrokeStyleSerialize {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
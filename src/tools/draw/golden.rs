@@ -0,0 +1,262 @@
+// Pixel comparison of every tool drawn with a set of styles against
+// the reference images in tests/golden. If a rendering change is
+// intended, regenerate the images with:
+//
+//     SKETCHOVER_BLESS=1 cargo test golden
+//
+// and check the new images in with the change.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use raqote::{DrawTarget, LineCap, LineJoin, SolidSource, StrokeStyle};
+
+use crate::render::Scene;
+use crate::tools::draw::circle::Circle;
+use crate::tools::draw::draw::Draw;
+use crate::tools::draw::line::Line;
+use crate::tools::draw::pen::Pen;
+use crate::tools::draw::rekt::Rect;
+use crate::tools::draw::scripted::{Paint, PathOp, Script, Scripted};
+use crate::tools::Tool;
+
+const SIZE: u32 = 64;
+/// Max difference of a channel before the pixel counts as changed
+const TOLERANCE: u8 = 2;
+/// How many changed pixels we accept, antialiasing can differ slightly
+const MAX_CHANGED: usize = 8;
+
+type Create = fn((f64, f64), Draw) -> Box<dyn Tool>;
+/// A tool and the motions used to draw it
+type ToolCase = (&'static str, Create, Vec<(f64, f64)>);
+
+fn tools() -> Vec<ToolCase> {
+    vec![
+        (
+            "pen",
+            |pos, draw| Box::new(Pen::new(pos, draw)),
+            vec![(8., 8.), (20., 40.), (32., 16.), (44., 52.), (56., 24.)],
+        ),
+        (
+            "line",
+            |pos, draw| Box::new(Line::new(pos, draw)),
+            vec![(8., 12.), (30., 30.), (56., 44.)],
+        ),
+        (
+            "rect",
+            |pos, draw| Box::new(Rect::new(pos, draw)),
+            vec![(12., 14.), (30., 30.), (52., 48.)],
+        ),
+        (
+            "circle",
+            |pos, draw| Box::new(Circle::new(pos, draw)),
+            vec![(12., 32.), (40., 32.), (52., 32.)],
+        ),
+        (
+            "scripted",
+            |pos, draw| {
+                Box::new(Scripted::new(
+                    "stamp".to_owned(),
+                    draw,
+                    Box::new(Stamp(pos)),
+                ))
+            },
+            vec![(10., 10.), (30., 40.), (50., 54.)],
+        ),
+    ]
+}
+
+/// A filled triangle and a curve, ending in a circle at the pointer
+struct Stamp((f64, f64));
+
+impl Script for Stamp {
+    fn update(&mut self, motion: (f64, f64)) -> Result<Vec<Paint>, Box<dyn std::error::Error>> {
+        let (x0, y0) = (self.0 .0 as f32, self.0 .1 as f32);
+        let (x1, y1) = (motion.0 as f32, motion.1 as f32);
+        Ok(vec![
+            Paint::Fill(vec![
+                PathOp::MoveTo(x0, y0),
+                PathOp::LineTo(x0, y1),
+                PathOp::LineTo((x0 + x1) / 2., y1),
+                PathOp::Close,
+            ]),
+            Paint::Stroke(vec![
+                PathOp::MoveTo(x0, y0),
+                PathOp::QuadTo(x1, y0, x1, y1 - 6.),
+                PathOp::Arc(x1, y1, 6., 0., 2. * std::f32::consts::PI),
+            ]),
+        ])
+    }
+}
+
+fn styles() -> Vec<(&'static str, Draw)> {
+    let style = |width, cap, join, dash_array: Vec<f32>| StrokeStyle {
+        width,
+        cap,
+        join,
+        miter_limit: 10.,
+        dash_array,
+        dash_offset: 0.,
+    };
+    let red = SolidSource::from_unpremultiplied_argb(255, 255, 0, 0);
+    vec![
+        (
+            "thin",
+            Draw {
+                style: style(1., LineCap::Butt, LineJoin::Miter, Vec::new()),
+                color: red,
+            },
+        ),
+        (
+            "round",
+            Draw {
+                style: style(8., LineCap::Round, LineJoin::Round, Vec::new()),
+                color: SolidSource::from_unpremultiplied_argb(255, 0, 200, 0),
+            },
+        ),
+        (
+            "square",
+            Draw {
+                style: style(6., LineCap::Square, LineJoin::Bevel, Vec::new()),
+                color: SolidSource::from_unpremultiplied_argb(255, 0, 0, 255),
+            },
+        ),
+        (
+            "dashed",
+            Draw {
+                style: style(3., LineCap::Butt, LineJoin::Miter, vec![6., 4.]),
+                color: red,
+            },
+        ),
+        (
+            "alpha",
+            Draw {
+                style: style(5., LineCap::Round, LineJoin::Miter, Vec::new()),
+                color: SolidSource::from_unpremultiplied_argb(128, 255, 128, 0),
+            },
+        ),
+    ]
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn render(create: Create, motions: &[(f64, f64)], draw: Draw) -> Vec<u8> {
+    let mut tool = create(motions[0], draw);
+    for motion in motions.iter() {
+        tool.update(*motion);
+    }
+    let draws = vec![tool];
+
+    let mut buffer = vec![0u32; (SIZE * SIZE) as usize];
+    let mut dt = DrawTarget::from_backing(SIZE as i32, SIZE as i32, &mut buffer[..]);
+    let transparent = SolidSource {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+    Scene::new(transparent, &draws).render(&mut dt);
+    to_rgba(&buffer)
+}
+
+// premultiplied argb to rgba, the same way the image is saved
+fn to_rgba(buffer: &[u32]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(buffer.len() * 4);
+    for pixel in buffer {
+        let a = (pixel >> 24) & 0xff;
+        let unmultiply = |c: u32| {
+            (c * 255 + a / 2)
+                .checked_div(a)
+                .map_or(0, |c| c.min(255) as u8)
+        };
+        rgba.push(unmultiply((pixel >> 16) & 0xff));
+        rgba.push(unmultiply((pixel >> 8) & 0xff));
+        rgba.push(unmultiply(pixel & 0xff));
+        rgba.push(a as u8);
+    }
+    rgba
+}
+
+fn write_png(path: &Path, rgba: &[u8]) {
+    let file = File::create(path).expect("Couldn't create image");
+    let mut encoder = png::Encoder::new(BufWriter::new(file), SIZE, SIZE);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().expect("Couldn't write png header");
+    writer
+        .write_image_data(rgba)
+        .expect("Couldn't write png data");
+}
+
+fn read_png(path: &Path) -> Vec<u8> {
+    let file = File::open(path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing, run with SKETCHOVER_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .expect("Couldn't read png");
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).expect("Couldn't decode png");
+    assert_eq!(
+        (info.width, info.height, info.color_type),
+        (SIZE, SIZE, png::ColorType::Rgba),
+        "{} has the wrong format",
+        path.display()
+    );
+    buf.truncate(info.buffer_size());
+    buf
+}
+
+fn changed_pixels(expected: &[u8], actual: &[u8]) -> usize {
+    expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .filter(|(e, a)| {
+            e.iter()
+                .zip(a.iter())
+                .any(|(e, a)| e.abs_diff(*a) > TOLERANCE)
+        })
+        .count()
+}
+
+#[test]
+fn golden_images() {
+    let bless = std::env::var_os("SKETCHOVER_BLESS").is_some();
+    let mut failed = Vec::new();
+
+    for (tool, create, motions) in tools() {
+        for (style, draw) in styles() {
+            let name = format!("{tool}-{style}");
+            let path = golden_path(&name);
+            let actual = render(create, &motions, draw);
+
+            if bless {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                write_png(&path, &actual);
+                continue;
+            }
+
+            let expected = read_png(&path);
+            let changed = changed_pixels(&expected, &actual);
+            if changed > MAX_CHANGED {
+                let out = std::env::temp_dir().join(format!("sketchover-{name}.png"));
+                write_png(&out, &actual);
+                failed.push(format!(
+                    "{name}: {changed} pixels differ, got {}",
+                    out.display()
+                ));
+            }
+        }
+    }
+
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
@@ -1,17 +1,25 @@
+use std::time::Instant;
+
 use raqote::DrawTarget;
-use smithay_client_toolkit::seat::keyboard::Modifiers;
 
-use crate::tools::draw::draw::Draw;
-use crate::tools::Tool;
-use serde::Serialize;
+use crate::tools::behaviour::{Behaviour, SNAP_ANGLE};
+use crate::tools::draw::draw::{self, Draw};
+use crate::tools::{Bounds, Tool};
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Line {
     draw: Draw,
     start: (f64, f64),
     stop: (f64, f64),
-    real_stop: Option<(f64, f64)>,
-    straight: bool,
+    // where we started, start moves when drawing from the centre
+    #[serde(skip)]
+    anchor: (f64, f64),
+    #[serde(skip)]
+    behaviour: Behaviour,
+    // shapes read from a save count from when they were read
+    #[serde(skip, default = "Instant::now")]
+    created: Instant,
 }
 
 impl Line {
@@ -21,41 +29,41 @@ impl Line {
             draw,
             start: pos,
             stop: (pos.0 + 20.0, pos.1 + 20.0),
-            real_stop: None,
-            straight: false,
+            anchor: pos,
+            behaviour: Behaviour::default(),
+            created: Instant::now(),
         }
     }
 }
 
+#[typetag::serialize(name = "line")]
 impl Tool for Line {
     fn update(&mut self, motion: (f64, f64)) {
-        if self.straight {
-            self.real_stop = Some(motion);
-            let x_length = f64::abs(motion.0 - self.start.0);
-            let y_length = f64::abs(motion.1 - self.start.1);
-            if x_length > y_length {
-                self.stop = (motion.0, self.start.1);
+        let (mut dx, mut dy) = draw::diff(self.anchor, motion);
+        if self.behaviour.straighten {
+            if dx.abs() > dy.abs() {
+                dy = 0.;
             } else {
-                self.stop = (self.start.0, motion.1);
+                dx = 0.;
             }
-        } else {
-            self.stop = motion;
+        } else if self.behaviour.snap {
+            let step = SNAP_ANGLE.to_radians();
+            let angle = (dy.atan2(dx) / step).round() * step;
+            let length = dx.hypot(dy);
+            (dx, dy) = (length * angle.cos(), length * angle.sin());
         }
-    }
 
-    // Fix this later, modifier shouldn't work like this
-    // it should be a setter of some sort
-    fn modifier(&mut self, modifier: &Modifiers) {
-        // don't hardcode this
-        if modifier.shift {
-            self.straight = true;
-            self.update(self.stop);
+        self.stop = (self.anchor.0 + dx, self.anchor.1 + dy);
+        self.start = if self.behaviour.from_centre {
+            (self.anchor.0 - dx, self.anchor.1 - dy)
         } else {
-            self.straight = true;
-            if let Some(stop) = self.real_stop {
-                self.update(stop);
-            }
-        }
+            self.anchor
+        };
+    }
+
+    fn behaviour(&mut self, behaviour: &Behaviour) -> bool {
+        self.behaviour = *behaviour;
+        true
     }
 
     fn draw(&self, dt: &mut DrawTarget<&mut [u32]>) {
@@ -72,4 +80,82 @@ impl Tool for Line {
             &raqote::DrawOptions::new(),
         );
     }
+
+    fn style(&self) -> &Draw {
+        &self.draw
+    }
+
+    fn style_mut(&mut self) -> &mut Draw {
+        &mut self.draw
+    }
+
+    fn name(&self) -> &str {
+        "line"
+    }
+
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        Bounds::around([self.start, self.stop])
+    }
+
+    fn translate(&mut self, offset: (f64, f64)) {
+        self.start = draw::translate(self.start, offset);
+        self.stop = draw::translate(self.stop, offset);
+        self.anchor = draw::translate(self.anchor, offset);
+    }
+
+    fn snap_points(&self) -> Vec<(f64, f64)> {
+        let middle = (
+            (self.start.0 + self.stop.0) / 2.,
+            (self.start.1 + self.stop.1) / 2.,
+        );
+        vec![self.start, middle, self.stop]
+    }
+
+    fn snaps_to_shapes(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Line;
+    use crate::tools::behaviour::Behaviour;
+    use crate::tools::Tool;
+
+    fn line(behaviour: Behaviour, motion: (f64, f64)) -> ((f64, f64), (f64, f64)) {
+        let mut line = Line::new((10., 10.), Default::default());
+        line.behaviour(&behaviour);
+        line.update(motion);
+        let round = |p: (f64, f64)| ((p.0 * 100.).round() / 100., (p.1 * 100.).round() / 100.);
+        (round(line.start), round(line.stop))
+    }
+
+    #[test]
+    fn behaviours() {
+        let none = Behaviour::default();
+        assert_eq!(line(none, (40., 20.)), ((10., 10.), (40., 20.)));
+
+        let straighten = Behaviour {
+            straighten: true,
+            ..none
+        };
+        assert_eq!(line(straighten, (40., 20.)), ((10., 10.), (40., 10.)));
+        assert_eq!(line(straighten, (12., -20.)), ((10., 10.), (10., -20.)));
+
+        let snap = Behaviour { snap: true, ..none };
+        // 5 degrees snaps to 0
+        let ((_, _), (x, y)) = line(snap, (110., 10. + 100. * 5f64.to_radians().tan()));
+        assert_eq!(y, 10.);
+        assert!(x > 100.);
+
+        let centre = Behaviour {
+            from_centre: true,
+            ..none
+        };
+        assert_eq!(line(centre, (20., 30.)), ((0., -10.), (20., 30.)));
+    }
 }
@@ -1,5 +1,9 @@
 pub mod circle;
+#[allow(clippy::module_inception)]
 pub mod draw;
+#[cfg(test)]
+mod golden;
 pub mod line;
 pub mod pen;
 pub mod rekt;
+pub mod scripted;
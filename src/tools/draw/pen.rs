@@ -1,13 +1,18 @@
+use std::time::Instant;
+
 use raqote::DrawTarget;
 
-use crate::tools::draw::draw::Draw;
-use crate::tools::Tool;
-use serde::Serialize;
+use crate::tools::draw::draw::{self, Draw};
+use crate::tools::{Bounds, Tool};
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Pen {
     draw: Draw,
     lines: Vec<(f64, f64)>,
+    // shapes read from a save count from when they were read
+    #[serde(skip, default = "Instant::now")]
+    created: Instant,
 }
 
 impl Pen {
@@ -15,11 +20,13 @@ impl Pen {
         Pen {
             draw,
             lines: Vec::new(),
+            created: Instant::now(),
         }
     }
 }
 
 // we need a way to create a new pen from an identifier
+#[typetag::serialize(name = "pen")]
 impl Tool for Pen {
     fn update(&mut self, motion: (f64, f64)) {
         self.lines.push(motion);
@@ -46,4 +53,30 @@ impl Tool for Pen {
             &raqote::DrawOptions::new(),
         );
     }
+
+    fn style(&self) -> &Draw {
+        &self.draw
+    }
+
+    fn style_mut(&mut self) -> &mut Draw {
+        &mut self.draw
+    }
+
+    fn name(&self) -> &str {
+        "pen"
+    }
+
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        Bounds::around(self.lines.iter().copied())
+    }
+
+    fn translate(&mut self, offset: (f64, f64)) {
+        for pos in self.lines.iter_mut() {
+            *pos = draw::translate(*pos, offset);
+        }
+    }
 }
@@ -1,17 +1,27 @@
+use std::time::Instant;
+
 use raqote::DrawTarget;
 
+use crate::tools::behaviour::Behaviour;
 use crate::tools::draw::draw;
 use crate::tools::draw::draw::Draw;
-use crate::tools::Tool;
-use serde::Serialize;
+use crate::tools::{Bounds, Tool};
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Rect {
     draw: Draw,
     start: (f64, f64),
     stop: (f64, f64),
-    _square: bool,
     _fill: bool,
+    // where we started, start moves when drawing from the centre
+    #[serde(skip)]
+    anchor: (f64, f64),
+    #[serde(skip)]
+    behaviour: Behaviour,
+    // shapes read from a save count from when they were read
+    #[serde(skip, default = "Instant::now")]
+    created: Instant,
 }
 
 impl Rect {
@@ -20,15 +30,36 @@ impl Rect {
             draw,
             start: pos,
             stop: (20.0, 20.0),
-            _square: false,
             _fill: false,
+            anchor: pos,
+            behaviour: Behaviour::default(),
+            created: Instant::now(),
         }
     }
 }
 
+#[typetag::serialize(name = "rect")]
 impl Tool for Rect {
+    // start is the corner and stop is the size
     fn update(&mut self, motion: (f64, f64)) {
-        self.stop = draw::diff(self.start, motion);
+        let (mut dx, mut dy) = draw::diff(self.anchor, motion);
+        if self.behaviour.square {
+            let side = dx.abs().max(dy.abs());
+            dx = side.copysign(dx);
+            dy = side.copysign(dy);
+        }
+        if self.behaviour.from_centre {
+            self.start = (self.anchor.0 - dx, self.anchor.1 - dy);
+            self.stop = (2. * dx, 2. * dy);
+        } else {
+            self.start = self.anchor;
+            self.stop = (dx, dy);
+        }
+    }
+
+    fn behaviour(&mut self, behaviour: &Behaviour) -> bool {
+        self.behaviour = *behaviour;
+        true
     }
 
     fn draw(&self, dt: &mut DrawTarget<&mut [u32]>) {
@@ -49,4 +80,34 @@ impl Tool for Rect {
             &raqote::DrawOptions::new(),
         );
     }
+
+    fn style(&self) -> &Draw {
+        &self.draw
+    }
+
+    fn style_mut(&mut self) -> &mut Draw {
+        &mut self.draw
+    }
+
+    fn name(&self) -> &str {
+        "rect"
+    }
+
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        Bounds::around([self.start, draw::translate(self.start, self.stop)])
+    }
+
+    // stop is the size, so it stays
+    fn translate(&mut self, offset: (f64, f64)) {
+        self.start = draw::translate(self.start, offset);
+        self.anchor = draw::translate(self.anchor, offset);
+    }
+
+    fn snaps_to_shapes(&self) -> bool {
+        true
+    }
 }
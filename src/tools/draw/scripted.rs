@@ -0,0 +1,176 @@
+// A tool where something outside of the crate, like a lua script,
+// decides the shape. The script returns the paths to paint after every
+// update and only the paths are saved, so a save can be drawn without
+// the script.
+
+use std::error;
+use std::time::Instant;
+
+use raqote::{DrawOptions, DrawTarget, Path, PathBuilder, Source};
+
+use crate::tools::draw::draw::Draw;
+use crate::tools::{Bounds, Tool};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathOp {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    QuadTo(f32, f32, f32, f32),
+    CubicTo(f32, f32, f32, f32, f32, f32),
+    /// Centre, radius, start angle and sweep in radians
+    Arc(f32, f32, f32, f32, f32),
+    Close,
+}
+
+impl PathOp {
+    // the points the path goes through or is pulled towards, curves
+    // don't go outside of their control points
+    fn extent(&self) -> Vec<(f32, f32)> {
+        match *self {
+            PathOp::MoveTo(x, y) | PathOp::LineTo(x, y) => vec![(x, y)],
+            PathOp::QuadTo(cx, cy, x, y) => vec![(cx, cy), (x, y)],
+            PathOp::CubicTo(cx1, cy1, cx2, cy2, x, y) => vec![(cx1, cy1), (cx2, cy2), (x, y)],
+            PathOp::Arc(x, y, r, _, _) => vec![(x - r, y - r), (x + r, y + r)],
+            PathOp::Close => Vec::new(),
+        }
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        match self {
+            PathOp::MoveTo(x, y) | PathOp::LineTo(x, y) | PathOp::Arc(x, y, ..) => {
+                *x += dx;
+                *y += dy;
+            }
+            PathOp::QuadTo(cx, cy, x, y) => {
+                (*cx, *cy, *x, *y) = (*cx + dx, *cy + dy, *x + dx, *y + dy);
+            }
+            PathOp::CubicTo(cx1, cy1, cx2, cy2, x, y) => {
+                (*cx1, *cy1) = (*cx1 + dx, *cy1 + dy);
+                (*cx2, *cy2) = (*cx2 + dx, *cy2 + dy);
+                (*x, *y) = (*x + dx, *y + dy);
+            }
+            PathOp::Close => {}
+        }
+    }
+}
+
+/// A path and how to paint it, with the style and color of the tool
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Paint {
+    Stroke(Vec<PathOp>),
+    Fill(Vec<PathOp>),
+}
+
+pub trait Script {
+    /// The pointer moved to motion, returns what the shape looks like now
+    fn update(&mut self, motion: (f64, f64)) -> Result<Vec<Paint>, Box<dyn error::Error>>;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Scripted {
+    /// The name the tool was registered with
+    name: String,
+    draw: Draw,
+    paints: Vec<Paint>,
+    // restored tools can't be updated, they are done anyway
+    #[serde(skip)]
+    script: Option<Box<dyn Script>>,
+    // shapes read from a save count from when they were read
+    #[serde(skip, default = "Instant::now")]
+    created: Instant,
+}
+
+impl Scripted {
+    pub fn new(name: String, draw: Draw, script: Box<dyn Script>) -> Self {
+        Scripted {
+            name,
+            draw,
+            paints: Vec::new(),
+            script: Some(script),
+            created: Instant::now(),
+        }
+    }
+
+    pub fn paints(&self) -> &[Paint] {
+        &self.paints
+    }
+}
+
+fn build(ops: &[PathOp]) -> Path {
+    let mut pb = PathBuilder::new();
+    for op in ops {
+        match *op {
+            PathOp::MoveTo(x, y) => pb.move_to(x, y),
+            PathOp::LineTo(x, y) => pb.line_to(x, y),
+            PathOp::QuadTo(cx, cy, x, y) => pb.quad_to(cx, cy, x, y),
+            PathOp::CubicTo(cx1, cy1, cx2, cy2, x, y) => pb.cubic_to(cx1, cy1, cx2, cy2, x, y),
+            PathOp::Arc(x, y, r, start, sweep) => pb.arc(x, y, r, start, sweep),
+            PathOp::Close => pb.close(),
+        }
+    }
+    pb.finish()
+}
+
+#[typetag::serialize(name = "scripted")]
+impl Tool for Scripted {
+    fn update(&mut self, motion: (f64, f64)) {
+        if let Some(script) = self.script.as_mut() {
+            match script.update(motion) {
+                Ok(paints) => self.paints = paints,
+                Err(e) => log::error!("Tool {} failed: {e}", self.name),
+            }
+        }
+    }
+
+    fn draw(&self, dt: &mut DrawTarget<&mut [u32]>) {
+        let source = Source::Solid(self.draw.color);
+        for paint in self.paints.iter() {
+            match paint {
+                Paint::Stroke(ops) => {
+                    dt.stroke(&build(ops), &source, &self.draw.style, &DrawOptions::new())
+                }
+                Paint::Fill(ops) => dt.fill(&build(ops), &source, &DrawOptions::new()),
+            }
+        }
+    }
+
+    fn style(&self) -> &Draw {
+        &self.draw
+    }
+
+    fn style_mut(&mut self) -> &mut Draw {
+        &mut self.draw
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        let points = self
+            .paints
+            .iter()
+            .flat_map(|paint| match paint {
+                Paint::Stroke(ops) | Paint::Fill(ops) => ops.iter(),
+            })
+            .flat_map(PathOp::extent);
+        Bounds::around(points.map(|(x, y)| (x as f64, y as f64)))
+    }
+
+    fn translate(&mut self, offset: (f64, f64)) {
+        let (dx, dy) = (offset.0 as f32, offset.1 as f32);
+        for paint in self.paints.iter_mut() {
+            let (Paint::Stroke(ops) | Paint::Fill(ops)) = paint;
+            for op in ops.iter_mut() {
+                op.translate(dx, dy);
+            }
+        }
+    }
+}
@@ -1,17 +1,28 @@
+use std::time::Instant;
+
 use raqote::{DrawTarget, Point};
 
-use erased_serde::serialize_trait_object;
-use smithay_client_toolkit::seat::keyboard::Modifiers;
+use crate::tools::behaviour::Behaviour;
+use crate::tools::draw::draw::Draw;
 
+pub mod behaviour;
 pub mod draw;
+pub mod registry;
+pub mod snap;
 
-// #[typetag::serde(tag = "apa")]
-pub trait Tool: Send + erased_serde::Serialize {
+// Tools are saved with their name in the "tool" field, so we know
+// what to create when we restore them. Only the registry reads them.
+#[typetag::serialize(tag = "tool")]
+pub trait Tool {
     /// When the mouse is moved, the currently active (if there is one) tool
     /// will be updated,
     fn update(&mut self, motion: (f64, f64));
     /// convert the tool to a set of paths
     fn draw(&self, dt: &mut DrawTarget<&mut [u32]>);
+    /// The style and color the tool is drawn with
+    fn style(&self) -> &Draw;
+    /// Changing the style while drawing changes the shape being drawn
+    fn style_mut(&mut self) -> &mut Draw;
 
     // fn save(&self);
     //
@@ -19,10 +30,32 @@ pub trait Tool: Send + erased_serde::Serialize {
     // where
     //     Self: Sized;
 
-    /// A tool sould be able to have a set of modifiers width
-    /// it. Atm these are hardcoded against keys but in the future
-    /// these should be mapped against a config value.
-    fn modifier(&mut self, _: &Modifiers) {}
+    /// Called when the held modifiers change while drawing. Returns if
+    /// the shape depends on the behaviour, then the runtime calls update
+    /// with the current position right after, so tools like the pen that
+    /// add a point on every update return false.
+    fn behaviour(&mut self, _: &Behaviour) -> bool {
+        false
+    }
+    /// The name the tool is created with
+    fn name(&self) -> &str;
+    /// When the shape was started, shapes read from a save count from
+    /// when they were read
+    fn created(&self) -> Instant;
+    /// The box around the shape, not counting the width of the stroke
+    fn bounds(&self) -> Option<Bounds>;
+    /// Move the shape by offset
+    fn translate(&mut self, offset: (f64, f64));
+    /// Points other shapes snap and align to, the corners and the
+    /// middle of the edges of the bounds unless the tool knows better
+    fn snap_points(&self) -> Vec<(f64, f64)> {
+        self.bounds().map(|b| b.points()).unwrap_or_default()
+    }
+    /// If the positions passed to update snap to other shapes, not only
+    /// to the grid
+    fn snaps_to_shapes(&self) -> bool {
+        false
+    }
     /// For drawing the size of the figure
     /// The function returns the size (width, height) and a position
     /// to put the text
@@ -31,4 +64,74 @@ pub trait Tool: Send + erased_serde::Serialize {
     }
 }
 
-serialize_trait_object!(Tool);
+/// An axis aligned box, x and y is the top left corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Bounds {
+    /// The smallest box around the points, None if there are no points
+    pub fn around<I: IntoIterator<Item = (f64, f64)>>(points: I) -> Option<Bounds> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), p| {
+            (
+                (min.0.min(p.0), min.1.min(p.1)),
+                (max.0.max(p.0), max.1.max(p.1)),
+            )
+        });
+        Some(Bounds {
+            x: min.0,
+            y: min.1,
+            width: max.0 - min.0,
+            height: max.1 - min.1,
+        })
+    }
+
+    /// The corners, the middle of the edges and the centre
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        let xs = [self.x, self.x + self.width / 2., self.x + self.width];
+        let ys = [self.y, self.y + self.height / 2., self.y + self.height];
+        xs.iter()
+            .flat_map(|&x| ys.iter().map(move |&y| (x, y)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bounds;
+    use crate::tools::draw::draw::Draw;
+    use crate::tools::registry::ToolRegistry;
+
+    #[test]
+    fn bounds_and_translate() {
+        let registry = ToolRegistry::default();
+        for name in registry.names() {
+            let mut tool = registry.create(name, (10., 10.), Draw::default()).unwrap();
+            tool.update((30., 40.));
+            assert_eq!(tool.name(), name);
+            let bounds = tool.bounds().unwrap();
+            if name == "line" || name == "rect" {
+                assert_eq!(
+                    bounds,
+                    Bounds {
+                        x: 10.,
+                        y: 10.,
+                        width: 20.,
+                        height: 30.
+                    }
+                );
+            }
+
+            tool.translate((5., -5.));
+            let moved = tool.bounds().unwrap();
+            assert_eq!((moved.x, moved.y), (bounds.x + 5., bounds.y - 5.), "{name}");
+            assert_eq!((moved.width, moved.height), (bounds.width, bounds.height));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Maps tool names to how the tool is created and how it's read from a
+// save file. The tools in sketchover are always there and plugins add
+// their own, so the config, lua, ipc and the saves all agree on what a
+// tool is.
+
+use std::error;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::tools::draw::circle::Circle;
+use crate::tools::draw::draw::Draw;
+use crate::tools::draw::line::Line;
+use crate::tools::draw::pen::Pen;
+use crate::tools::draw::rekt::Rect;
+use crate::tools::draw::scripted::Scripted;
+use crate::tools::Tool;
+
+/// Start a tool at pos
+pub type Create = Rc<dyn Fn((f64, f64), Draw) -> Box<dyn Tool>>;
+
+/// Read a saved tool, the json object it was saved as
+pub type Read = fn(Value) -> Result<Box<dyn Tool>, serde_json::Error>;
+
+struct Entry {
+    name: String,
+    create: Option<Create>,
+    read: Option<Read>,
+}
+
+pub struct ToolRegistry {
+    // in the order they were added, so listing them is stable
+    entries: Vec<Entry>,
+}
+
+/// The error for a name that isn't in the registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownTool(pub String);
+
+impl fmt::Display for UnknownTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not a tool", self.0)
+    }
+}
+
+impl error::Error for UnknownTool {}
+
+fn read<T: Tool + DeserializeOwned + 'static>(
+    value: Value,
+) -> Result<Box<dyn Tool>, serde_json::Error> {
+    Ok(Box::new(serde_json::from_value::<T>(value)?))
+}
+
+fn create<T: Tool + 'static>(new: fn((f64, f64), Draw) -> T) -> Create {
+    Rc::new(move |pos, draw| Box::new(new(pos, draw)))
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        let mut registry = ToolRegistry {
+            entries: Vec::new(),
+        };
+        registry.add("pen", Some(create(Pen::new)), Some(read::<Pen>));
+        registry.add("line", Some(create(Line::new)), Some(read::<Line>));
+        registry.add("rect", Some(create(Rect::new)), Some(read::<Rect>));
+        registry.add("circle", Some(create(Circle::new)), Some(read::<Circle>));
+        // made by lua and plugins, they only add a way to create them
+        registry.add("scripted", None, Some(read::<Scripted>));
+        registry
+    }
+}
+
+impl ToolRegistry {
+    fn add(&mut self, name: &str, create: Option<Create>, read: Option<Read>) {
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            create,
+            read,
+        });
+    }
+
+    fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Add a tool that can be drawn with. Returns false if the name is
+    /// taken.
+    pub fn register(&mut self, name: &str, create: Create) -> bool {
+        if self.entry(name).is_some() {
+            return false;
+        }
+        self.add(name, Some(create), None);
+        true
+    }
+
+    /// If there is a tool called name that can be drawn with
+    pub fn contains(&self, name: &str) -> bool {
+        self.entry(name).is_some_and(|entry| entry.create.is_some())
+    }
+
+    /// The tools that can be drawn with
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.create.is_some())
+            .map(|entry| entry.name.as_str())
+    }
+
+    /// Create the tool called name, starting at pos
+    pub fn create(
+        &self,
+        name: &str,
+        pos: (f64, f64),
+        draw: Draw,
+    ) -> Result<Box<dyn Tool>, UnknownTool> {
+        match self.entry(name).and_then(|entry| entry.create.as_ref()) {
+            Some(create) => Ok(create(pos, draw)),
+            None => Err(UnknownTool(name.to_owned())),
+        }
+    }
+
+    /// Read a saved tool, the tool field says what it is
+    pub fn read(&self, value: Value) -> Result<Box<dyn Tool>, Box<dyn error::Error>> {
+        let name = value
+            .get("tool")
+            .and_then(Value::as_str)
+            .ok_or("The saved tool has no name")?;
+        match self.entry(name).and_then(|entry| entry.read) {
+            Some(read) => Ok(read(value)?),
+            None => Err(Box::new(UnknownTool(name.to_owned()))),
+        }
+    }
+}
+
+/// Deserialize the drawings of a save file through the registry. Only
+/// tools built into sketchover can be saved, so the default one is used.
+pub fn deserialize_draws<'de, D>(deserializer: D) -> Result<Vec<Box<dyn Tool>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let registry = ToolRegistry::default();
+    Vec::<Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|value| registry.read(value).map_err(de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{ToolRegistry, UnknownTool};
+    use crate::tools::draw::draw::Draw;
+    use crate::tools::draw::line::Line;
+
+    #[test]
+    fn create_and_read() {
+        let mut registry = ToolRegistry::default();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["pen", "line", "rect", "circle"]
+        );
+        assert!(registry
+            .create("scripted", (0., 0.), Draw::default())
+            .is_err());
+        assert_eq!(
+            registry.create("spray", (0., 0.), Draw::default()).err(),
+            Some(UnknownTool("spray".to_owned()))
+        );
+
+        assert!(!registry.register("pen", Rc::new(|pos, draw| Box::new(Line::new(pos, draw)))));
+        assert!(registry.register(
+            "segment",
+            Rc::new(|pos, draw| Box::new(Line::new(pos, draw)))
+        ));
+        assert!(registry.contains("segment"));
+
+        let mut circle = registry
+            .create("circle", (1., 1.), Draw::default())
+            .unwrap();
+        circle.update((3., 3.));
+        let saved = serde_json::to_value(&circle).unwrap();
+        let read = registry.read(saved).unwrap();
+        assert_eq!(read.name(), "circle");
+        assert_eq!(read.bounds(), circle.bounds());
+
+        let err = registry
+            .read(serde_json::json!({ "tool": "spray" }))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "spray is not a tool");
+    }
+}
@@ -0,0 +1,174 @@
+// Snapping moves the positions tools are updated with onto the grid of
+// the background and in line with the points of the other shapes. A shape
+// only has to be close on one axis to snap to it, so shapes line up, and a
+// guide is drawn from the point that was lined up with.
+
+use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source, StrokeStyle};
+use serde::{Deserialize, Serialize};
+
+use crate::background::Background;
+use crate::tools::Tool;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Snap {
+    /// Nothing snaps unless enabled, it's toggled while running
+    pub enabled: bool,
+    /// Snap to the grid of the background, shown or not
+    pub grid: bool,
+    /// Snap to the points of the other shapes
+    pub shapes: bool,
+    /// How close in pixels a point of a shape has to be to snap to it
+    pub distance: f64,
+}
+
+impl Default for Snap {
+    fn default() -> Self {
+        Snap {
+            enabled: false,
+            grid: true,
+            shapes: true,
+            distance: 8.,
+        }
+    }
+}
+
+/// A line from a point of a shape to the position in line with it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guide {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+}
+
+const GUIDE_COLOR: SolidSource = SolidSource {
+    r: 0xff,
+    g: 0x00,
+    b: 0xff,
+    a: 0xff,
+};
+
+impl Snap {
+    /// Snap pos to the grid of background and to the points of shapes.
+    /// Returns where pos snapped to and the guides to show.
+    pub fn snap(
+        &self,
+        pos: (f64, f64),
+        background: &Background,
+        shapes: &[Box<dyn Tool>],
+    ) -> ((f64, f64), Vec<Guide>) {
+        if !self.enabled {
+            return (pos, Vec::new());
+        }
+        let mut snapped = if self.grid {
+            background.grid_point(pos)
+        } else {
+            pos
+        };
+        if !self.shapes {
+            return (snapped, Vec::new());
+        }
+
+        let points: Vec<_> = shapes
+            .iter()
+            .flat_map(|shape| shape.snap_points())
+            .collect();
+        // the point closest on the axis, the closest one if several are
+        let closest = |axis: fn((f64, f64)) -> f64| {
+            points
+                .iter()
+                .copied()
+                .filter(|&p| (axis(p) - axis(pos)).abs() <= self.distance)
+                .min_by(|&a, &b| {
+                    let key = |p: (f64, f64)| ((axis(p) - axis(pos)).abs(), dist(p, pos));
+                    key(a)
+                        .partial_cmp(&key(b))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+        };
+        let (x, y) = (closest(|p| p.0), closest(|p| p.1));
+        if let Some(p) = x {
+            snapped.0 = p.0;
+        }
+        if let Some(p) = y {
+            snapped.1 = p.1;
+        }
+        let guides = [x, y]
+            .into_iter()
+            .flatten()
+            .map(|from| Guide { from, to: snapped })
+            .collect();
+        (snapped, guides)
+    }
+}
+
+fn dist(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+pub fn draw_guides(guides: &[Guide], dt: &mut DrawTarget<&mut [u32]>) {
+    if guides.is_empty() {
+        return;
+    }
+    let mut pb = PathBuilder::new();
+    for guide in guides {
+        pb.move_to(guide.from.0 as f32, guide.from.1 as f32);
+        pb.line_to(guide.to.0 as f32, guide.to.1 as f32);
+    }
+    let style = StrokeStyle {
+        width: 1.,
+        dash_array: vec![4., 4.],
+        ..Default::default()
+    };
+    dt.stroke(
+        &pb.finish(),
+        &Source::Solid(GUIDE_COLOR),
+        &style,
+        &DrawOptions::new(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Guide, Snap};
+    use crate::background::Background;
+    use crate::tools::draw::draw::Draw;
+    use crate::tools::draw::line::Line;
+    use crate::tools::Tool;
+
+    #[test]
+    fn snap_to_grid_and_shapes() {
+        let background = Background {
+            spacing: 10.,
+            ..Default::default()
+        };
+        let mut line = Line::new((0., 0.), Draw::default());
+        line.update((40., 40.));
+        let shapes: Vec<Box<dyn Tool>> = vec![Box::new(line)];
+
+        let mut snap = Snap::default();
+        assert_eq!(snap.snap((23., 57.), &background, &shapes).0, (23., 57.));
+
+        snap.enabled = true;
+        snap.shapes = false;
+        assert_eq!(snap.snap((23., 57.), &background, &shapes).0, (20., 60.));
+
+        // x is in line with the middle of the line, y only on the grid
+        snap.shapes = true;
+        assert_eq!(
+            snap.snap((23., 57.), &background, &shapes),
+            (
+                (20., 60.),
+                vec![Guide {
+                    from: (20., 20.),
+                    to: (20., 60.)
+                }]
+            )
+        );
+
+        snap.grid = false;
+        let (pos, guides) = snap.snap((41., 38.), &background, &shapes);
+        assert_eq!(pos, (40., 40.));
+        assert_eq!(guides.len(), 2);
+        assert_eq!(snap.snap((63., 57.), &background, &shapes).0, (63., 57.));
+    }
+}
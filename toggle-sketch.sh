@@ -10,7 +10,7 @@ if pgrep -x "sketchover" >/dev/null; then
 	# If it's running, we do we tell it to toggles pass-through
 	# when pass-through is enabled, drawing is disabled but we can
 	# interact with the windows behind it
-	pkill -TSTP sketchover
+	sketchover msg passthrough
 else
 	sketchover
 fi